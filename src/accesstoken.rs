@@ -0,0 +1,121 @@
+//! Short-lived tokens a client exchanges one of its secrets for, then presents on the retrieval
+//! endpoints in place of the secret (OAuth2 client credentials style).
+//!
+//! `akt_<claims>.<signature>`: base64url JSON claims, and their HMAC-SHA256 with a key derived
+//! from the cookie secret, so nothing about a token is stored. Bumping the token generation of
+//! the client revokes every token issued to it.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+
+pub const PREFIX: &str = "akt_";
+const KEY_LABEL: &[u8] = b"akm access token";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Claims {
+    pub client: i64,
+    /// Token generation of the client when the token was issued
+    pub generation: i64,
+    /// Keys the token gives access to
+    pub keys: Vec<i64>,
+    /// Unix time
+    pub expires: i64,
+}
+
+/// Whether a bearer credential is a token rather than a secret
+pub fn is_token(s: &str) -> bool {
+    s.starts_with(PREFIX)
+}
+
+/// The key tokens are signed with: HKDF of the cookie secret, so it differs from the one signing
+/// cookies and a cookie can't pass for a token, or the other way around
+pub fn derive_key(secret: &[u8]) -> [u8; 32] {
+    let prk = ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA256, &[]).extract(secret);
+    let mut key = [0; 32];
+    prk.expand(&[KEY_LABEL], ring::hkdf::HKDF_SHA256)
+        .and_then(|okm| okm.fill(&mut key))
+        .expect("32 bytes is the length of HKDF_SHA256");
+    key
+}
+
+pub fn issue(key: &[u8], claims: &Claims) -> String {
+    let claims =
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("claims are always valid json"));
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
+    let signature = URL_SAFE_NO_PAD.encode(ring::hmac::sign(&key, claims.as_bytes()));
+    format!("{PREFIX}{claims}.{signature}")
+}
+
+/// The claims of a token signed with `key` and not yet expired at `now`
+pub fn verify(key: &[u8], token: &str, now: i64) -> Option<Claims> {
+    let (claims, signature) = token.strip_prefix(PREFIX)?.split_once('.')?;
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
+    ring::hmac::verify(
+        &key,
+        claims.as_bytes(),
+        &URL_SAFE_NO_PAD.decode(signature).ok()?,
+    )
+    .ok()?;
+
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).ok()?).ok()?;
+    (claims.expires > now).then_some(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn claims() -> Claims {
+        Claims {
+            client: 3,
+            generation: 1,
+            keys: vec![4, 5],
+            expires: NOW + 60,
+        }
+    }
+
+    #[test]
+    fn issued_tokens_verify() {
+        let key = derive_key(b"cookie secret");
+        let token = issue(&key, &claims());
+        assert!(is_token(&token));
+        assert_eq!(verify(&key, &token, NOW), Some(claims()));
+    }
+
+    #[test]
+    fn edited_claims_are_refused() {
+        let key = derive_key(b"cookie secret");
+        let token = issue(&key, &claims());
+        let (_, signature) = token.split_once('.').unwrap();
+        let edited = Claims {
+            keys: vec![4, 5, 6],
+            ..claims()
+        };
+        let edited = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&edited).unwrap());
+        assert_eq!(
+            verify(&key, &format!("{PREFIX}{edited}.{signature}"), NOW),
+            None
+        );
+        assert_eq!(verify(&key, &token[..token.len() - 1], NOW), None);
+        assert_eq!(verify(&key, &token.replace('.', ""), NOW), None);
+    }
+
+    #[test]
+    fn expired_tokens_are_refused() {
+        let key = derive_key(b"cookie secret");
+        let token = issue(&key, &claims());
+        assert!(verify(&key, &token, NOW + 59).is_some());
+        assert_eq!(verify(&key, &token, NOW + 60), None);
+    }
+
+    #[test]
+    fn tokens_of_another_key_are_refused() {
+        let key = derive_key(b"cookie secret");
+        let token = issue(&derive_key(b"another secret"), &claims());
+        assert_eq!(verify(&key, &token, NOW), None);
+        // nor signed with the cookie secret itself
+        let token = issue(b"cookie secret", &claims());
+        assert_eq!(verify(&key, &token, NOW), None);
+    }
+}
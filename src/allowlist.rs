@@ -0,0 +1,161 @@
+//! IP ranges a client (and each of its links) may use its secrets from.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::StatusCode,
+};
+use color_eyre::Result;
+use ipnet::IpNet;
+
+use crate::state::AppState;
+
+pub(crate) fn to_db(ranges: &[IpNet]) -> String {
+    serde_json::to_string(ranges).expect("a list of ranges is always valid json")
+}
+
+pub(crate) fn from_db(ranges: &str) -> Result<Vec<IpNet>> {
+    serde_json::from_str(ranges).map_err(color_eyre::Report::from)
+}
+
+/// Every list must contain the address, an empty list allows anything
+pub fn is_allowed(ip: Option<IpAddr>, lists: &[&[IpNet]]) -> bool {
+    lists
+        .iter()
+        .all(|l| l.is_empty() || ip.is_some_and(|ip| l.iter().any(|r| r.contains(&ip))))
+}
+
+/// The address a request comes from: the peer, unless it is one of the trusted proxies,
+/// in which case `X-Forwarded-For` is walked back until an untrusted hop
+pub fn resolve(peer: Option<IpAddr>, forwarded_for: &[&str], trusted: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|r| r.contains(ip));
+
+    let mut ip = peer?;
+    let hops = forwarded_for
+        .iter()
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .rev();
+    for hop in hops {
+        if !is_trusted(&ip) {
+            break;
+        }
+        // a garbled header can't be trusted to say where the request comes from
+        ip = hop.parse().ok()?;
+    }
+    Some(ip)
+}
+
+/// Address of the caller, `None` when it can't be told
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0.ip());
+        let forwarded_for = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .collect::<Vec<_>>();
+        Ok(Self(resolve(
+            peer,
+            &forwarded_for,
+            &state.config.trusted_proxies,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    fn nets(s: &[&str]) -> Vec<IpNet> {
+        s.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_forwarded_for() {
+        let trusted = nets(&["10.0.0.0/8"]);
+        assert_eq!(
+            resolve(ip("192.0.2.1"), &["198.51.100.7"], &trusted),
+            ip("192.0.2.1")
+        );
+        assert_eq!(
+            resolve(ip("192.0.2.1"), &["garbage"], &trusted),
+            ip("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_are_walked_back() {
+        let trusted = nets(&["10.0.0.0/8"]);
+        // the client forged the first hop, each proxy appended the address it saw
+        assert_eq!(
+            resolve(
+                ip("10.0.0.2"),
+                &["203.0.113.9, 198.51.100.7", "10.0.0.1"],
+                &trusted
+            ),
+            ip("198.51.100.7")
+        );
+        // every hop trusted, the first one is all there is
+        assert_eq!(
+            resolve(ip("10.0.0.2"), &["10.0.0.3,10.0.0.1"], &trusted),
+            ip("10.0.0.3")
+        );
+        // no header, the proxy itself
+        assert_eq!(resolve(ip("10.0.0.2"), &[], &trusted), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn garbled_hop_resolves_to_nothing() {
+        let trusted = nets(&["10.0.0.0/8"]);
+        assert_eq!(
+            resolve(ip("10.0.0.2"), &["198.51.100.7, not-an-ip"], &trusted),
+            None
+        );
+        assert_eq!(resolve(ip("10.0.0.2"), &[""], &trusted), None);
+        // past an untrusted hop, the rest isn't read
+        assert_eq!(
+            resolve(ip("10.0.0.2"), &["not-an-ip, 198.51.100.7"], &trusted),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn empty_list_allows_anything() {
+        assert!(is_allowed(ip("192.0.2.1"), &[&[]]));
+        assert!(is_allowed(None, &[&[], &[]]));
+        assert!(is_allowed(None, &[]));
+    }
+
+    #[test]
+    fn every_list_must_contain_the_address() {
+        let client = nets(&["192.0.2.0/24"]);
+        let link = nets(&["192.0.2.0/28"]);
+        assert!(is_allowed(ip("192.0.2.1"), &[&client, &link]));
+        assert!(!is_allowed(ip("192.0.2.100"), &[&client, &link]));
+        assert!(is_allowed(ip("192.0.2.100"), &[&client, &[]]));
+    }
+
+    #[test]
+    fn unknown_address_is_refused_by_a_list() {
+        assert!(!is_allowed(None, &[&nets(&["0.0.0.0/0"])]));
+        assert!(!is_allowed(None, &[&[], &nets(&["192.0.2.0/24"])]));
+    }
+}
@@ -1,30 +1,48 @@
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
 };
 use chrono::DateTime;
+use ipnet::IpNet;
 use serde::de::DeserializeOwned;
+use tracing::error;
 
 use super::ErrorToStatusCode;
 use crate::{
-    database::{clients::ClientId, keys::KeyId},
+    database::{
+        Metadata,
+        clientkeys::LinkLimits,
+        clients::{ClientCertificate, ClientId, ClientUpdate, ClientUpdateConflict},
+        keys::KeyId,
+    },
     state::AppState,
 };
 
 #[derive(serde::Serialize, Clone, Debug, utoipa::ToSchema)]
 pub struct ClientInfo {
     pub id: i64,
+    pub namespace: String,
     pub desc: String,
     pub name: String,
+    pub metadata: Metadata,
+    /// IP ranges the client may use its secrets from, anywhere when empty
+    #[schema(value_type = Vec<String>)]
+    pub allowed_cidrs: Vec<IpNet>,
+    /// TLS client certificate the client may authenticate with instead of its secrets
+    pub certificate: ClientCertificate,
 }
 
 impl From<crate::database::clients::TableClients> for ClientInfo {
     fn from(v: crate::database::clients::TableClients) -> Self {
         Self {
             id: v.id.inner(),
+            namespace: v.namespace,
             name: v.name,
             desc: v.description,
+            metadata: v.metadata,
+            allowed_cidrs: v.allowed_cidrs,
+            certificate: v.certificate,
         }
     }
 }
@@ -33,27 +51,125 @@ impl From<crate::database::clients::TableClients> for ClientInfo {
 pub struct ClientInfoNoId {
     pub name: String,
     pub desc: String,
+    /// `default` on creation, left untouched when missing on update.
+    /// A client can't be moved while it is linked to keys of its current namespace
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Left untouched when missing
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
+    /// IP ranges the client may use its secrets from, anywhere when empty. Left untouched when missing
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<String>>)]
+    pub allowed_cidrs: Option<Vec<IpNet>>,
+    /// TLS client certificate the client may authenticate with instead of its secrets.
+    /// Left untouched when missing
+    #[serde(default)]
+    pub certificate: Option<ClientCertificate>,
+}
+
+// checked before anything is written, `Err` when the fingerprint isn't a SHA-256
+fn normalize_certificate(certificate: ClientCertificate) -> Result<ClientCertificate, StatusCode> {
+    certificate.normalize().ok_or_else(|| {
+        error!("client certificate fingerprint isn't a hex SHA-256");
+        StatusCode::BAD_REQUEST
+    })
 }
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 #[utoipa::path(post, path = "/client/new", 
     responses(
         (status = OK, body = i64, description = "new Client Created"),
+        (status = BAD_REQUEST, description = "Invalid Request: namespace must be alphanumeric or `-`/`_`, certificate fingerprint must be a hex SHA-256, runbook must be an http(s) URL"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = CONFLICT, description = "The certificate is already registered on another client"),
     ),
     request_body(content = inline(ClientInfoNoId), content_type = "application/json")
 )]
 pub async fn client_new(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Json(new_info): Json<ClientInfoNoId>,
 ) -> Result<Json<i64>, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    db.create_client(new_info.name.as_str(), new_info.desc.as_str())
+    let namespace = new_info
+        .namespace
+        .unwrap_or_else(super::utils::default_namespace);
+    if !super::utils::is_valid_namespace(&namespace) {
+        error!("new client namespace isn't only alphanumeric or `_`/`-`: {namespace}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if new_info
+        .metadata
+        .as_ref()
+        .is_some_and(|m| !m.is_valid_runbook())
+    {
+        error!("new client runbook isn't an http(s) URL");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let certificate = new_info
+        .certificate
+        .map(normalize_certificate)
+        .transpose()?;
+
+    let client = db
+        .create_client(
+            user.org(),
+            namespace,
+            new_info.name.as_str(),
+            new_info.desc.as_str(),
+        )
+        .await
+        .to_status()?;
+    if let Some(metadata) = new_info.metadata {
+        db.update_client_metadata(client, &metadata)
+            .await
+            .to_status()?;
+    }
+    if let Some(ranges) = new_info.allowed_cidrs {
+        db.update_client_allowed_cidrs(client, &ranges)
+            .await
+            .to_status()?;
+    }
+    if let Some(certificate) = certificate
+        && !db
+            .update_client_certificate(client, &certificate)
+            .await
+            .to_status()?
+    {
+        error!("client certificate already registered on another client");
+        // not left half created, with the certificate the request was refused for
+        db.remove_client(client).await.to_status()?;
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(Json(client.inner()))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/client/list", 
+    responses(
+        (status = OK, body = inline(Vec<ClientInfo>), description = "Every client matching the filter"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(super::utils::MetadataFilter),
+)]
+pub async fn client_list(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Query(filter): Query<super::utils::MetadataFilter>,
+) -> Result<Json<Vec<ClientInfo>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    db.get_all_clients(user.org())
         .await
         .to_status()
-        .map(ClientId::inner)
+        .map(|v| {
+            v.into_iter()
+                .filter(|c| filter.matches(&c.namespace, &c.name, &c.description, &c.metadata))
+                .map(ClientInfo::from)
+        })
+        .map(Iterator::collect)
         .map(Json)
 }
 
@@ -69,13 +185,13 @@ pub async fn client_new(
     ))
 ]
 pub async fn client_info(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path(client): Path<i64>,
 ) -> Result<Json<ClientInfo>, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    super::utils::client_from_raw(db, client)
+    super::utils::client_from_raw(db, user.org(), client)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)
@@ -87,8 +203,10 @@ pub async fn client_info(
 #[utoipa::path(put, path = "/client/{client}/", 
     responses(
         (status = OK, description = "Info of a client)"),
+        (status = BAD_REQUEST, description = "Invalid Request: namespace must be alphanumeric or `-`/`_`, certificate fingerprint must be a hex SHA-256, runbook must be an http(s) URL"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
         (status = NOT_FOUND, description = "The client doesn't exist"),
+        (status = CONFLICT, description = "The client is still linked to keys of its namespace, or the certificate is registered on another client"),
     ),
     params(
         ("client" = i64, Path, description = "The client"),
@@ -97,22 +215,63 @@ pub async fn client_info(
     )
 ]
 pub async fn client_set_info(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path(client): Path<i64>,
     Json(info): Json<ClientInfoNoId>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    let client = super::utils::client_from_raw(db, client)
+    let client = super::utils::client_from_raw(db, user.org(), client)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    db.update_client_info(client.id, info.name, info.desc)
+    if info
+        .metadata
+        .as_ref()
+        .is_some_and(|m| !m.is_valid_runbook())
+    {
+        error!("client runbook isn't an http(s) URL");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let namespace = info.namespace.filter(|ns| *ns != client.namespace);
+    if let Some(namespace) = &namespace
+        && !super::utils::is_valid_namespace(namespace)
+    {
+        error!("client namespace isn't only alphanumeric or `_`/`-`: {namespace}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let certificate = info.certificate.map(normalize_certificate).transpose()?;
+    match db
+        .update_client(
+            client.id,
+            &ClientUpdate {
+                namespace: namespace.as_deref(),
+                name: &info.name,
+                desc: &info.desc,
+                metadata: info.metadata.as_ref(),
+                allowed_cidrs: info.allowed_cidrs.as_deref(),
+                certificate: certificate.as_ref(),
+            },
+        )
         .await
-        .to_status()
-        .map(|_| StatusCode::OK)
+        .to_status()?
+    {
+        Ok(()) => (),
+        Err(ClientUpdateConflict::LinkedOutsideNamespace) => {
+            error!(
+                "client {} is still linked to keys of another namespace",
+                client.id.inner()
+            );
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(ClientUpdateConflict::CertificateTaken) => {
+            error!("client certificate already registered on another client");
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+    Ok(StatusCode::OK)
 }
 
 #[derive(serde::Serialize, Clone, Debug, utoipa::ToSchema)]
@@ -135,18 +294,18 @@ pub struct KeyInfo {
     ))
 ]
 pub async fn client_list_keys(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path(client): Path<i64>,
 ) -> Result<Json<Vec<KeyInfo>>, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    let client = super::utils::client_from_raw(db, client)
+    let client = super::utils::client_from_raw(db, user.org(), client)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    db.get_all_keys_from_client(client.id)
+    db.get_all_keys_from_client(user.org(), client.id)
         .await
         .to_status()
         .map(|v| {
@@ -172,12 +331,12 @@ pub async fn client_list_keys(
     )
 )]
 pub async fn client_delete(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((client,)): Path<(i64,)>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
-    let client = super::utils::client_from_raw(db, client)
+    let client = super::utils::client_from_raw(db, user.org(), client)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -201,12 +360,12 @@ pub async fn client_delete(
     )
 )]
 pub async fn client_delete_key(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((client, key)): Path<(i64, i64)>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
-    let k = super::utils::clientkey_from_client_and_key(db, client, key)
+    let k = super::utils::clientkey_from_client_and_key(db, user.org(), client, key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -221,7 +380,7 @@ pub async fn client_delete_key(
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 #[utoipa::path(get, path = "/client/{client}/key/{key}/secret", 
     responses(
-        (status = OK, body = String, description = "Secret to actually get the key"),
+        (status = OK, body = String, description = "Secret to actually get the key, wrap it (`/client/{client}/key/{key}/wrap`) to hand it to a new server"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
         (status = NOT_FOUND, description = "The client or key (or the key<->client relation) doesn't exist "),
     ),
@@ -231,13 +390,13 @@ pub async fn client_delete_key(
     ))
 ]
 pub async fn client_get_secret(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((client, key)): Path<(i64, i64)>,
 ) -> Result<String, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    super::utils::clientkey_from_client_and_key(db, client, key)
+    super::utils::clientkey_from_client_and_key(db, user.org(), client, key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)
@@ -247,7 +406,7 @@ pub async fn client_get_secret(
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 #[utoipa::path(put, path = "/client/{client}/key/{key}/new_secret", 
     responses(
-        (status = OK, description = "Secret was updated to the value that has been returned"),
+        (status = OK, description = "Secret was updated to the value that has been returned, and the access tokens of the client revoked"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
         (status = NOT_FOUND, description = "The client or the key associated with the client doesn't exist"),
     ),
@@ -257,12 +416,12 @@ pub async fn client_get_secret(
     ))
 ]
 pub async fn client_new_secret(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((client, key)): Path<(i64, i64)>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
-    let client_key = super::utils::clientkey_from_client_and_key(db, client, key)
+    let client_key = super::utils::clientkey_from_client_and_key(db, user.org(), client, key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -270,7 +429,80 @@ pub async fn client_new_secret(
     db.update_client_secret(client_key.id)
         .await
         .to_status()?
-        .ok_or(StatusCode::NOT_FOUND)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // tokens exchanged for the old secret, which may have leaked, go with it
+    db.rotate_client_tokens(client_key.client_id)
+        .await
+        .to_status()?;
+    Ok(StatusCode::OK)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/client/{client}/key/{key}/cidrs", 
+    responses(
+        (status = OK, description = "The IP ranges of the link were replaced"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The client or the key associated with the client doesn't exist"),
+    ),
+    params(
+        ("client" = i64, Path, description = "The client"),
+        ("key" = i64, Path, description = "The key"),
+    ),
+    request_body(content = Vec<String>, description = "IP ranges the link may be used from, on top of the client's own. Anywhere when empty", content_type = "application/json")
+    )
+]
+pub async fn client_set_key_cidrs(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((client, key)): Path<(i64, i64)>,
+    Json(ranges): Json<Vec<IpNet>>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+    let client_key = super::utils::clientkey_from_client_and_key(db, user.org(), client, key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.update_client_key_allowed_cidrs(client_key.id, &ranges)
+        .await
+        .to_status()
+        .map(|_| StatusCode::OK)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/client/{client}/key/{key}/limits", 
+    responses(
+        (status = OK, description = "The limits of the link were replaced"),
+        (status = BAD_REQUEST, description = "The limits expire before being valid, or allow no use"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The client or the key associated with the client doesn't exist"),
+    ),
+    params(
+        ("client" = i64, Path, description = "The client"),
+        ("key" = i64, Path, description = "The key"),
+    ),
+    request_body(content = inline(LinkLimits), description = "When, and how many times, the link can be used. Uses so far are kept", content_type = "application/json")
+    )
+]
+pub async fn client_set_key_limits(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((client, key)): Path<(i64, i64)>,
+    Json(limits): Json<LinkLimits>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+    if !limits.is_valid() {
+        error!("link limits expire before being valid, or allow no use");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let client_key = super::utils::clientkey_from_client_and_key(db, user.org(), client, key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.update_client_key_limits(client_key.id, &limits)
+        .await
+        .to_status()
         .map(|_| StatusCode::OK)
 }
 
@@ -278,6 +510,7 @@ pub async fn client_new_secret(
 #[utoipa::path(put, path = "/client/{client}/key/{key}/link", 
     responses(
         (status = OK, description = "The key was associated with the client"),
+        (status = BAD_REQUEST, description = "The client and the key are not in the same namespace, or the limits expire before being valid or allow no use"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
         (status = NOT_FOUND, description = "The client or the key associated with the client doesn't exist"),
         (status = CONFLICT, description = "The client+key was already associated"),
@@ -285,33 +518,101 @@ pub async fn client_new_secret(
     params(
         ("client" = i64, Path, description = "The client"),
         ("key" = i64, Path, description = "The key"),
-    ))
+    ),
+    request_body(content = Option<LinkLimits>, description = "When, and how many times, the link can be used. Without bound when missing", content_type = "application/json")
+    )
 ]
 pub async fn client_new_key(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((client, key)): Path<(i64, i64)>,
+    limits: Option<Json<LinkLimits>>,
 ) -> Result<StatusCode, StatusCode> {
-    let client = super::utils::client_from_raw(&state.db, client)
+    let limits = limits.map(|Json(l)| l).unwrap_or_default();
+    if !limits.is_valid() {
+        error!("link limits expire before being valid, or allow no use");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let client = super::utils::client_from_raw(&state.db, user.org(), client)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
-    let key = super::utils::key_from_raw(&state.db, key)
+    let key = super::utils::key_from_raw(&state.db, user.org(), key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
+    if client.namespace != key.namespace {
+        error!(
+            "client {} ({}) can't be linked to key {} ({})",
+            client.id.inner(),
+            client.namespace,
+            key.id.inner(),
+            key.namespace
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
-    let client_key =
-        super::utils::clientkey_from_client_and_key(&state.db, client.id.inner(), key.id.inner())
-            .await
-            .to_status()?;
+    let client_key = super::utils::clientkey_from_client_and_key(
+        &state.db,
+        user.org(),
+        client.id.inner(),
+        key.id.inner(),
+    )
+    .await
+    .to_status()?;
     if client_key.is_some() {
         return Err(StatusCode::CONFLICT);
     }
-    let _client_key = state
+    // the namespaces are compared again, one of them may have just moved
+    let Some((client_key, _)) = state
         .db
         .create_clientkey(client.id, key.id)
         .await
-        .to_status()?;
+        .to_status()?
+    else {
+        error!(
+            "client {} and key {} aren't in the same namespace anymore",
+            client.id.inner(),
+            key.id.inner()
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    if limits != LinkLimits::default() {
+        state
+            .db
+            .update_client_key_limits(client_key, &limits)
+            .await
+            .to_status()?;
+    }
     Ok(StatusCode::OK)
 }
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/client/{client}/rotate",
+    responses(
+        (status = OK, description = "Every access token issued to the client has been revoked"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The client doesn't exist"),
+    ),
+    params(
+        ("client" = i64, Path, description = "The client"),
+    ))
+]
+pub async fn client_rotate(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path(client): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let client = super::utils::client_from_raw(db, user.org(), client)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.rotate_client_tokens(client.id)
+        .await
+        .to_status()?
+        .then_some(StatusCode::OK)
+        .ok_or(StatusCode::NOT_FOUND)
+}
@@ -1,26 +1,135 @@
 use axum::{
     Json,
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
 };
 use tracing::error;
 
-use crate::database::Date;
+use crate::certs::CertInfo;
+use crate::database::{
+    Date, Metadata,
+    audit::{AuditActor, AuditSeverity},
+    keyfields::{FieldKind, TableKeysFields},
+    keyfiles::TableKeysFiles,
+    keys::{KeyId, KeyKind, KeyUpdate, KeyUpdateConflict},
+};
+use crate::signing::SigningAlgorithm;
+use crate::ssh::{SshAlgorithm, SshPublicKey};
 use crate::{api::ErrorToStatusCode, state::AppState};
 
+const AUDIT_HONEYTOKEN_SET: &str = "key.honeytoken_set";
+const AUDIT_HONEYTOKEN_UNSET: &str = "key.honeytoken_unset";
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct KeyInfo {
+    pub namespace: String,
     pub name: String,
     pub desc: String,
+    pub kind: KeyKind,
     pub has_key: bool,
     pub rotate_at: Option<Date>,
     pub has_rotate_key: bool,
+    /// A canary, any access to it raises an alert
+    pub honeytoken: bool,
+    /// Signing keys publish their JWKS at `/.well-known/{jwks_id}/jwks.json`
+    pub jwks_id: Option<String>,
+    pub metadata: Metadata,
+}
+
+impl From<crate::database::keys::TableKeys> for KeyInfo {
+    fn from(key: crate::database::keys::TableKeys) -> Self {
+        Self {
+            desc: key.description,
+            kind: key.kind,
+            has_key: key.key.is_some(),
+            has_rotate_key: key.rotate_with.is_some(),
+            namespace: key.namespace,
+            name: key.name,
+            rotate_at: key.rotate_at,
+            honeytoken: key.honeytoken,
+            jwks_id: key.jwks_id,
+            metadata: key.metadata,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct KeyListEntry {
+    pub id: i64,
+    #[serde(flatten)]
+    pub info: KeyInfo,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct NewKeyInfo {
+    #[serde(default = "super::utils::default_namespace")]
+    pub namespace: String,
     pub name: String,
     pub desc: String,
+    /// `text` when missing
+    #[serde(default)]
+    pub kind: KeyKind,
+    /// Required by (and only used for) `signing` keys
+    #[serde(default)]
+    pub algorithm: Option<SigningAlgorithm>,
+    /// Only used for `ssh` keys, `ed25519` when missing
+    #[serde(default)]
+    pub ssh_algorithm: SshAlgorithm,
+    /// Only used for `text` keys, the policy to generate the secret with
+    #[serde(default)]
+    pub generate: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct KeyField {
+    pub name: String,
+    pub kind: FieldKind,
+    pub value: String,
+}
+
+impl From<TableKeysFields> for KeyField {
+    fn from(field: TableKeysFields) -> Self {
+        Self {
+            name: field.name,
+            kind: field.kind,
+            value: field.value,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct KeySetField {
+    #[serde(default)]
+    pub kind: FieldKind,
+    pub value: String,
+}
+
+/// How a client wants to receive a key made of fields
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyFormat {
+    /// A JSON object, field name to value
+    #[default]
+    Json,
+    /// One `NAME='value'` line per field, ready to be sourced by a shell
+    Env,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct KeyFormatQuery {
+    /// Only used by keys made of fields, text keys are always returned as is
+    #[serde(default)]
+    pub format: KeyFormat,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct KeyFileQuery {
+    /// Name given to the file when downloaded, no path allowed
+    pub filename: String,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
@@ -43,6 +152,12 @@ pub struct KeySetSecrets {
         with = "super::utils::double_option"
     )]
     rotate_with: Option<Option<String>>,
+    /// Policy to generate the secret with, instead of giving it
+    #[serde(default)]
+    generate_secret: Option<String>,
+    /// Policy to generate `rotate_with` with, instead of giving it
+    #[serde(default)]
+    generate_rotate_with: Option<String>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
@@ -50,12 +165,23 @@ pub struct KeyGetSecrets {
     secret: Option<String>,
     rotate_at: Option<Date>,
     rotate_with: Option<String>,
+    /// Every field of the key, empty unless it is made of fields
+    fields: Vec<KeyField>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct KeyInfoUpdate {
     pub name: String,
     pub desc: String,
+    /// Left untouched when missing, a key can't be moved while it is linked to clients of its current namespace
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Left untouched when missing
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
+    /// Left untouched when missing
+    #[serde(default)]
+    pub honeytoken: Option<bool>,
 }
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
@@ -70,23 +196,67 @@ pub struct KeyInfoUpdate {
     ),
 )]
 pub async fn key_info(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((key,)): Path<(i64,)>,
 ) -> Result<Json<KeyInfo>, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    super::utils::key_from_raw(db, key)
+    super::utils::key_from_raw(db, user.org(), key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)
-        .map(|key| KeyInfo {
-            desc: key.description,
-            has_key: key.key.is_some(),
-            has_rotate_key: key.rotate_with.is_some(),
-            name: key.name,
-            rotate_at: key.rotate_at,
+        .map(KeyInfo::from)
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/list", 
+    responses(
+        (status = OK, body = inline(Vec<KeyListEntry>), description = "Every key matching the filter (no secrets)"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(super::utils::MetadataFilter),
+)]
+pub async fn key_list(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Query(filter): Query<super::utils::MetadataFilter>,
+) -> Result<Json<Vec<KeyListEntry>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    db.get_all_keys(user.org())
+        .await
+        .to_status()
+        .map(|v| {
+            v.into_iter()
+                .filter(|k| filter.matches(&k.namespace, &k.name, &k.description, &k.metadata))
+                .map(|k| KeyListEntry {
+                    id: k.id.inner(),
+                    info: k.into(),
+                })
         })
+        .map(Iterator::collect)
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/report", 
+    responses(
+        (status = OK, body = inline(crate::report::KeyReport), description = "Text keys sharing a value, with a weak value, or without any (no secrets)"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+)]
+pub async fn key_report(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+) -> Result<Json<crate::report::KeyReport>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    db.get_all_keys(user.org())
+        .await
+        .to_status()
+        .map(|keys| crate::report::build(&keys))
         .map(Json)
 }
 
@@ -94,28 +264,83 @@ pub async fn key_info(
 #[utoipa::path(put, path = "/key/{key}/", 
     responses(
         (status = OK, description = "Key information updated (no secrets nor time to rotate)"),
+        (status = BAD_REQUEST, description = "Invalid Request: namespace must be alphanumeric or `-`/`_`, runbook must be an http(s) URL"),
         (status = NOT_FOUND, description = "Key not found"),
+        (status = CONFLICT, description = "A key with this name already exists in the namespace, or the key is still linked to clients"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
     ),
     request_body(content = inline(KeyInfoUpdate), content_type = "application/json")
 )]
 pub async fn key_set_info(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((key,)): Path<(i64,)>,
     Json(update): Json<KeyInfoUpdate>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    let key = super::utils::key_from_raw(db, key)
+    let key = super::utils::key_from_raw(db, user.org(), key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    db.update_key_info(key.id, update.name, update.desc)
+    if update
+        .metadata
+        .as_ref()
+        .is_some_and(|m| !m.is_valid_runbook())
+    {
+        error!("key runbook isn't an http(s) URL");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let namespace = update.namespace.filter(|ns| *ns != key.namespace);
+    if let Some(namespace) = &namespace
+        && !super::utils::is_valid_namespace(namespace)
+    {
+        error!("key namespace isn't only alphanumeric or `_`/`-`: {namespace}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let honeytoken = update.honeytoken.filter(|h| *h != key.honeytoken);
+    match db
+        .update_key(
+            key.id,
+            &KeyUpdate {
+                namespace: namespace.as_deref(),
+                name: &update.name,
+                desc: &update.desc,
+                metadata: update.metadata.as_ref(),
+                honeytoken,
+            },
+        )
         .await
-        .to_status()
-        .map(|_| StatusCode::OK)
+        .to_status()?
+    {
+        Ok(()) => (),
+        Err(KeyUpdateConflict::LinkedOutsideNamespace) => {
+            error!(
+                "key {} is still linked to clients of another namespace",
+                key.id.inner()
+            );
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(KeyUpdateConflict::NameTaken) => return Err(StatusCode::CONFLICT),
+    }
+    if let Some(honeytoken) = honeytoken {
+        // turning a canary off is the first thing to do before using the key unnoticed
+        db.log_audit(
+            key.org,
+            Some(key.id),
+            AuditActor::User(user.get_id().await),
+            if honeytoken {
+                AUDIT_HONEYTOKEN_SET
+            } else {
+                AUDIT_HONEYTOKEN_UNSET
+            },
+            AuditSeverity::Info,
+        )
+        .await
+        .to_status()?;
+    }
+    Ok(StatusCode::OK)
 }
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
@@ -130,18 +355,27 @@ pub async fn key_set_info(
     ),
 )]
 pub async fn key_delete(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((key,)): Path<(i64,)>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    let key = super::utils::key_from_raw(db, key)
+    let key = super::utils::key_from_raw(db, user.org(), key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
 
     db.delete_all_with_key_id(key.id).await.to_status()?;
+    db.delete_all_fields_with_key_id(key.id).await.to_status()?;
+    db.delete_file_with_key_id(key.id).await.to_status()?;
+    db.delete_cert_with_key_id(key.id).await.to_status()?;
+    db.delete_totp_with_key_id(key.id).await.to_status()?;
+    db.delete_all_versions_with_key_id(key.id)
+        .await
+        .to_status()?;
+    db.delete_all_leases_with_key_id(key.id).await.to_status()?;
+    db.delete_all_shares_with_key_id(key.id).await.to_status()?;
     db.remove_key(key.id).await.to_status()?;
     Ok(StatusCode::OK)
 }
@@ -149,7 +383,7 @@ pub async fn key_delete(
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 #[utoipa::path(put, path = "/key/{key}/rotate", 
     responses(
-        (status = OK, description = "Key Rotated"),
+        (status = OK, description = "Key Rotated (a managed key gets a new version, the previous ones of a signing key stay published for the grace period)"),
         (status = NOT_FOUND, description = "Key not found"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
     ),
@@ -158,28 +392,50 @@ pub async fn key_delete(
     ),
 )]
 pub async fn key_rotate(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((key,)): Path<(i64,)>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    let key = super::utils::key_from_raw(db, key)
+    let key = super::utils::key_from_raw(db, user.org(), key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
     dbg!(&key);
 
-    db.update_key_secrets(key.id, Some(key.rotate_with), Some(None), Some(None))
-        .await
-        .to_status()
-        .map(|_| StatusCode::OK)
+    if matches!(key.kind, KeyKind::Signing | KeyKind::Transit | KeyKind::Ssh) {
+        return crate::rotation::rotate_managed_key(&state, &key)
+            .await
+            .to_status()
+            .map(|_| StatusCode::OK);
+    }
+
+    db.update_key_secrets(
+        key.id,
+        Some(key.rotate_with.clone()),
+        Some(None),
+        Some(None),
+    )
+    .await
+    .to_status()?;
+    // the clients holding the previous secret learn it on their next renewal
+    db.revoke_key_leases(key.id).await.to_status()?;
+    refresh_key_cert(
+        &state,
+        key.id,
+        key.rotate_with.as_deref().map(str::as_bytes),
+        true,
+    )
+    .await
+    .map(|_| StatusCode::OK)
 }
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 #[utoipa::path(put, path = "/key/{key}/secret", 
     responses(
         (status = OK, description = "Key Rotated"),
+        (status = BAD_REQUEST, description = "Invalid Request: a value and a policy were both given, or the policy is unknown"),
         (status = NOT_FOUND, description = "Key not found"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
     ),
@@ -189,22 +445,50 @@ pub async fn key_rotate(
     request_body(content = inline(KeySetSecrets), content_type = "application/json")
 )]
 pub async fn key_update_secret(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Path((key,)): Path<(i64,)>,
-    Json(update): Json<KeySetSecrets>,
+    Json(mut update): Json<KeySetSecrets>,
 ) -> Result<StatusCode, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    let key = super::utils::key_from_raw(db, key)
+    let key = super::utils::key_from_raw(db, user.org(), key)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    db.update_key_secrets(key.id, update.secret, update.rotate_at, update.rotate_with)
-        .await
-        .to_status()
-        .map(|_| StatusCode::OK)
+    for (value, policy) in [
+        (&mut update.secret, &update.generate_secret),
+        (&mut update.rotate_with, &update.generate_rotate_with),
+    ] {
+        let Some(policy) = policy else { continue };
+        if value.is_some() {
+            error!("both a value and a policy to generate it were given");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        *value = Some(Some(super::secret::generate(&state, policy)?));
+    }
+
+    let derive_rotate_at = update.rotate_at.is_none();
+    db.update_key_secrets(
+        key.id,
+        update.secret.clone(),
+        update.rotate_at,
+        update.rotate_with,
+    )
+    .await
+    .to_status()?;
+    if let Some(secret) = update.secret {
+        db.revoke_key_leases(key.id).await.to_status()?;
+        refresh_key_cert(
+            &state,
+            key.id,
+            secret.as_deref().map(str::as_bytes),
+            derive_rotate_at,
+        )
+        .await?;
+    }
+    Ok(StatusCode::OK)
 }
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
@@ -219,89 +503,658 @@ pub async fn key_update_secret(
     ),
 )]
 pub async fn key_secret(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
     Path((key,)): Path<(i64,)>,
 ) -> Result<Json<KeyGetSecrets>, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    super::utils::key_from_raw(db, key)
+    let key = super::utils::key_from_raw(db, user.org(), key)
         .await
         .to_status()?
-        .ok_or(StatusCode::NOT_FOUND)
-        .map(|key| KeyGetSecrets {
-            secret: key.key,
-            rotate_at: key.rotate_at,
-            rotate_with: key.rotate_with,
-        })
-        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    crate::honeytoken::check(
+        &state,
+        &key,
+        AuditActor::User(user.get_id().await),
+        crate::honeytoken::Access::Revealed,
+        ip,
+    )
+    .await;
+
+    let fields = db
+        .get_all_key_fields(key.id)
+        .await
+        .to_status()?
+        .into_iter()
+        .map(KeyField::from)
+        .collect();
+    Ok(Json(KeyGetSecrets {
+        secret: key.key,
+        rotate_at: key.rotate_at,
+        rotate_with: key.rotate_with,
+        fields,
+    }))
 }
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 #[utoipa::path(post, path = "/key/new", 
     responses(
         (status = OK, body = i64, description = "Key was created"),
-        (status = BAD_REQUEST, description = "Invalid Request: name must be alphanumeric or `-`/`_`. description must be between 0 and 1024 characters"),
+        (status = BAD_REQUEST, description = "Invalid Request: name and namespace must be alphanumeric or `-`/`_`. description must be between 0 and 1024 characters. signing keys need an algorithm. only text keys can be generated, with a known policy"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = CONFLICT, description = "A key with this name already exists in the namespace"),
     ),
     request_body(content = inline(NewKeyInfo), content_type = "application/json")
 )]
 pub async fn key_new(
-    _: crate::auth::UserAuth,
+    user: crate::auth::UserAuth,
     State(state): State<crate::AppState>,
     Json(info): Json<NewKeyInfo>,
 ) -> Result<Json<i64>, StatusCode> {
     let AppState { ref db, .. } = state;
 
-    if !(info
-        .name
-        .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
-    {
+    if !super::utils::is_valid_name(&info.name) {
         error!(
             "new key name isn't only alphanumeric or `_`/`-`: {}",
             info.name
         );
         return Err(StatusCode::BAD_REQUEST);
     }
+    if !super::utils::is_valid_namespace(&info.namespace) {
+        error!(
+            "new key namespace isn't only alphanumeric or `_`/`-`: {}",
+            info.namespace
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
     if !(0..=1024).contains(&info.desc.chars().count()) {
         error!("new key description is too long");
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    db.create_key(info.name, info.desc, None, None, None)
+    let algorithm = match (info.kind, info.algorithm) {
+        (KeyKind::Signing, None) => {
+            error!("new signing key without an algorithm");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        (KeyKind::Signing, algorithm) => algorithm,
+        _ => None,
+    };
+
+    let secret = match (info.kind, info.generate) {
+        (_, None) => None,
+        (KeyKind::Text, Some(policy)) => Some(super::secret::generate(&state, &policy)?),
+        (_, Some(_)) => {
+            error!("only text keys can have their secret generated");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    let key = db
+        .create_key(
+            user.org(),
+            info.namespace,
+            &info.name,
+            info.desc,
+            info.kind,
+            secret,
+            None,
+            None,
+        )
         .await
+        .to_status()?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    let first_version = match (info.kind, algorithm) {
+        (KeyKind::Signing, Some(algorithm)) => {
+            Some(crate::rotation::new_signing_version(&state, key, algorithm).await)
+        }
+        (KeyKind::Transit, _) => Some(crate::rotation::new_transit_version(&state, key).await),
+        (KeyKind::Ssh, _) => {
+            Some(crate::rotation::new_ssh_version(&state, key, info.name, info.ssh_algorithm).await)
+        }
+        _ => None,
+    };
+    if let Some(Err(e)) = first_version {
+        error!("failed to generate the first version of the key: {e}");
+        db.remove_key(key).await.to_status()?;
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(Json(key.inner()))
+}
+
+// keep the parsed certificate of a key in sync with its new content,
+// and set `rotateAt` from its expiry unless the caller gave one explicitly
+async fn refresh_key_cert(
+    state: &AppState,
+    key: KeyId,
+    content: Option<&[u8]>,
+    derive_rotate_at: bool,
+) -> Result<(), StatusCode> {
+    let Some(cert) = content.and_then(CertInfo::parse) else {
+        state.db.delete_cert_with_key_id(key).await.to_status()?;
+        return Ok(());
+    };
+    state.db.set_key_cert(key, &cert).await.to_status()?;
+    if derive_rotate_at {
+        let rotate_at = cert.rotate_at(state.config.cert_expiry_days);
+        state
+            .db
+            .update_key_secrets(key, None, Some(Some(rotate_at)), None)
+            .await
+            .to_status()?;
+    }
+    Ok(())
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/certificate", 
+    responses(
+        (status = OK, body = inline(CertInfo), description = "The certificate held by the key"),
+        (status = NOT_FOUND, description = "Key not found, or it doesn't hold a certificate"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_certificate(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<CertInfo>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.fetch_key_cert(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)
+        .map(|c| Json(c.cert))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/ssh", 
+    responses(
+        (status = OK, body = inline(SshPublicKey), description = "The public half of the current SSH keypair, the private one only goes to linked clients"),
+        (status = NOT_FOUND, description = "Key not found, or it isn't an SSH key"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_ssh_public(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<SshPublicKey>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .filter(|k| k.kind == KeyKind::Ssh)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let version = db
+        .fetch_current_key_version(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    SshPublicKey::from_private(&version.material)
         .to_status()
-        .map(|k| k.inner())
         .map(Json)
 }
 
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct AuditEntry {
+    pub action: String,
+    /// Set when a user did it
+    pub user: Option<i64>,
+    /// Set when a client did it
+    pub client: Option<i64>,
+    /// Set when it was done through a share link
+    pub share: Option<i64>,
+    pub severity: crate::database::audit::AuditSeverity,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<crate::database::audit::TableAuditLog> for AuditEntry {
+    fn from(entry: crate::database::audit::TableAuditLog) -> Self {
+        use crate::database::audit::AuditActor;
+        let (user, client, share) = match entry.actor {
+            AuditActor::User(user) => (Some(user.inner()), None, None),
+            AuditActor::Client(client) => (None, Some(client.inner()), None),
+            AuditActor::Share(share) => (None, None, Some(share.inner())),
+        };
+        Self {
+            action: entry.action,
+            user,
+            client,
+            share,
+            severity: entry.severity,
+            at: entry.at,
+        }
+    }
+}
+
+/// How many entries `key_audit` returns
+const AUDIT_LIMIT: i64 = 100;
+
 #[cfg_attr(debug_assertions, axum::debug_handler)]
-#[utoipa::path(put, path = "/key/get", 
+#[utoipa::path(get, path = "/key/{key}/audit", 
     responses(
-        (status = OK, body = i64, description = "Key was created"),
-        (status = BAD_REQUEST, description = "Invalid Request: name must be alphanumeric or `-`/`_`. description must be between 0 and 1024 characters"),
+        (status = OK, body = inline(Vec<AuditEntry>), description = "The latest audited actions on the key, newest first"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_audit(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.get_audit_log_for_key(key.id, AUDIT_LIMIT)
+        .await
+        .to_status()
+        .map(|v| v.into_iter().map(AuditEntry::from).collect())
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/fields", 
+    responses(
+        (status = OK, body = inline(Vec<KeyField>), description = "Every field of the key"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_fields(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<Vec<KeyField>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    crate::honeytoken::check(
+        &state,
+        &key,
+        AuditActor::User(user.get_id().await),
+        crate::honeytoken::Access::Revealed,
+        ip,
+    )
+    .await;
+
+    db.get_all_key_fields(key.id)
+        .await
+        .to_status()
+        .map(|v| v.into_iter().map(KeyField::from).collect())
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/key/{key}/field/{name}", 
+    responses(
+        (status = OK, description = "Field created or replaced"),
+        (status = BAD_REQUEST, description = "Invalid Request: the key isn't made of fields, the name isn't a valid env variable name, or the value doesn't match the field kind"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+        ("name" = String, Path, description = "The field name"),
+    ),
+    request_body(content = inline(KeySetField), content_type = "application/json")
+)]
+pub async fn key_set_field(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key, name)): Path<(i64, String)>,
+    Json(field): Json<KeySetField>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if key.kind != KeyKind::Fields {
+        error!("key {} isn't made of fields", key.id.inner());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !super::utils::is_valid_field_name(&name) {
+        error!("field name isn't a valid env variable name: {name}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !field.kind.is_valid(&field.value) {
+        error!("field {name} isn't a valid {}", field.kind.as_str());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    db.set_key_field(key.id, name, field.kind, field.value)
+        .await
+        .to_status()
+        .map(|_| StatusCode::OK)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(delete, path = "/key/{key}/field/{name}/delete", 
+    responses(
+        (status = OK, description = "Field deleted"),
+        (status = NOT_FOUND, description = "Key or field not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+        ("name" = String, Path, description = "The field name"),
+    ),
+)]
+pub async fn key_delete_field(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key, name)): Path<(i64, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.remove_key_field(key.id, name)
+        .await
+        .to_status()?
+        .then_some(StatusCode::OK)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/key/{key}/file", 
+    responses(
+        (status = OK, description = "File uploaded, replacing the previous one"),
+        (status = BAD_REQUEST, description = "Invalid Request: the key doesn't hold a file, or the filename/content type is invalid"),
+        (status = PAYLOAD_TOO_LARGE, description = "The file is bigger than the configured limit"),
+        (status = NOT_FOUND, description = "Key not found"),
         (status = FORBIDDEN, description = "Invalid Auth cookie"),
     ),
-    request_body(content = String, content_type = "application/text")
+    params(
+        ("key" = i64, Path, description = "The key"),
+        KeyFileQuery,
+    ),
+    request_body(content = Vec<u8>, description = "The raw file, its `Content-Type` is kept", content_type = "application/octet-stream")
 )]
+pub async fn key_upload_file(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+    Query(query): Query<KeyFileQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if key.kind != KeyKind::File {
+        error!("key {} doesn't hold a file", key.id.inner());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !super::utils::is_valid_filename(&query.filename) {
+        error!("invalid filename: {}", query.filename);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let content_type = match headers.get(header::CONTENT_TYPE) {
+        Some(v) => v.to_str().map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => "application/octet-stream",
+    };
+
+    let content = axum::body::to_bytes(body, state.config.max_file_size)
+        .await
+        .map_err(|e| {
+            error!("file upload failed: {e}");
+            StatusCode::PAYLOAD_TOO_LARGE
+        })?;
+
+    db.set_key_file(key.id, query.filename, content_type, &content)
+        .await
+        .to_status()?;
+    refresh_key_cert(&state, key.id, Some(&content), true)
+        .await
+        .map(|_| StatusCode::OK)
+}
+
+// the file as an attachment, with its original content type
+fn file_response(file: TableKeysFiles) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, file.content_type),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file.filename),
+            ),
+        ],
+        file.content,
+    )
+        .into_response()
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/file", 
+    responses(
+        (status = OK, body = Vec<u8>, description = "The raw file, with its `Content-Type`", content_type = "application/octet-stream"),
+        (status = NOT_FOUND, description = "Key not found, or no file uploaded yet"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_download_file(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Response, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    crate::honeytoken::check(
+        &state,
+        &key,
+        AuditActor::User(user.get_id().await),
+        crate::honeytoken::Access::Revealed,
+        ip,
+    )
+    .await;
+
+    db.fetch_key_file(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)
+        .map(file_response)
+}
+
+// `NAME='value'` lines, single quotes closed, escaped and reopened
+fn fields_to_env(fields: &[TableKeysFields]) -> String {
+    fields
+        .iter()
+        .map(|f| format!("{}='{}'\n", f.name, f.value.replace('\'', r"'\''")))
+        .collect()
+}
+
+// what `get_key` returns for the key: its secret, its fields, its raw file or its SSH private key
+pub(super) async fn key_value(
+    state: &AppState,
+    key: &crate::database::keys::TableKeys,
+    format: KeyFormat,
+) -> Result<Response, StatusCode> {
+    match key.kind {
+        KeyKind::Text => Ok(key.key.clone().unwrap_or_default().into_response()),
+        KeyKind::Fields => {
+            let fields = state.db.get_all_key_fields(key.id).await.to_status()?;
+            match format {
+                KeyFormat::Json => Ok(Json(
+                    fields
+                        .into_iter()
+                        .map(|f| (f.name, f.value.into()))
+                        .collect::<serde_json::Map<_, _>>(),
+                )
+                .into_response()),
+                KeyFormat::Env => Ok((
+                    [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                    fields_to_env(&fields),
+                )
+                    .into_response()),
+            }
+        }
+        KeyKind::File => state
+            .db
+            .fetch_key_file(key.id)
+            .await
+            .to_status()?
+            .ok_or(StatusCode::NOT_FOUND)
+            .map(file_response),
+        KeyKind::Ssh => state
+            .db
+            .fetch_current_key_version(key.id)
+            .await
+            .to_status()?
+            .ok_or(StatusCode::NOT_FOUND)
+            .map(|v| {
+                String::from_utf8_lossy(&v.material)
+                    .into_owned()
+                    .into_response()
+            }),
+        KeyKind::Signing | KeyKind::Transit | KeyKind::Totp => {
+            error!("managed keys never leave akm");
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/key/get", 
+    responses(
+        (status = OK, body = String, description = "The key secret, its fields in the requested format, its raw file, or its SSH private key (OpenSSH format)",
+            headers(
+                ("x-akm-lease" = String, description = "For a leased key: renew the lease on `/lease/renew` before it expires, or discard the copy"),
+                ("x-akm-lease-expires" = String, description = "For a leased key: when the lease expires unless renewed (RFC 3339)"),
+            )),
+        (status = NOT_FOUND, description = "The key holds no file yet"),
+        (status = BAD_REQUEST, description = "The key is a managed (signing, transit or totp) key, it is never returned"),
+        (status = FORBIDDEN, description = "Invalid client secret or certificate, used from outside of the allowed IP ranges, or the link is outside of its validity or used up"),
+        (status = TOO_MANY_REQUESTS, description = "Too many requests from this address or with this secret, or locked out after unknown secrets, see `Retry-After`"),
+    ),
+    params(
+        KeyFormatQuery,
+        ("x-akm-key" = Option<i64>, Header, description = "The key to get when authenticating with a client certificate or an access token (for more than one key) instead of a secret"),
+        ("x-akm-link" = Option<i64>, Header, description = "The link whose secret signed the request, when signing instead of sending the secret"),
+        ("x-akm-timestamp" = Option<i64>, Header, description = "Unix time of a signed request"),
+        ("x-akm-nonce" = Option<String>, Header, description = "16 to 64 characters never used before by the link, for a signed request"),
+        ("x-akm-signature" = Option<String>, Header, description = "Hex HMAC-SHA256 with the secret of `METHOD\\npath?query\\ntimestamp\\nnonce`"),
+    ),
+    request_body(content = String, content_type = "application/text", description = "The client secret or an access token, empty when signing the request or authenticating with a client certificate")
+)]
+#[allow(clippy::too_many_arguments)]
 pub async fn get_key(
     State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    method: axum::http::Method,
+    axum::extract::OriginalUri(uri): axum::extract::OriginalUri,
+    headers: HeaderMap,
+    cert: Option<axum::Extension<crate::tls::PeerCertificate>>,
+    Query(query): Query<KeyFormatQuery>,
     secret: String,
-) -> Result<String, StatusCode> {
+) -> Result<Response, StatusCode> {
+    let secret = Some(secret.trim().to_string()).filter(|s| !s.is_empty());
+    let cert = cert.map(|axum::Extension(c)| c);
+    let Some(credentials) =
+        crate::auth::ClientCredentials::from_head(&method, &uri, &headers, cert.as_ref(), secret)
+    else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let (client, link) =
+        match crate::auth::ClientAuth::from_credentials(&state, credentials, ip).await {
+            Ok(client) => (client.client(), client.link()),
+            Err(response) => return Ok(response),
+        };
     let key = state
         .db
-        .get_client_key_from_secret(&secret)
+        .fetch_linked_key(link)
         .await
         .to_status()?
-        .map(|s| s.key_id)
         .ok_or(StatusCode::FORBIDDEN)?;
+    // refuses managed keys, before anything is counted
+    let mut response = key_value(&state, &key, query.format).await?;
+    crate::honeytoken::check(
+        &state,
+        &key,
+        AuditActor::Client(client),
+        crate::honeytoken::Access::Retrieved(link),
+        ip,
+    )
+    .await;
 
-    state
-        .db
-        .fetch_key(key)
-        .await
-        .to_status()?
-        .ok_or(StatusCode::FORBIDDEN)
-        .map(|s| s.key.unwrap_or_default())
+    // only a use once there is a value to return, a call to the wrong endpoint costs nothing
+    if !state.db.record_client_key_use(link).await.to_status()? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // sensitive keys are leased, the client must renew the lease to keep its copy
+    if let Some(ttl) = key.lease_ttl {
+        let link = state
+            .db
+            .fetch_client_key_by_id(link)
+            .await
+            .to_status()?
+            .ok_or(StatusCode::FORBIDDEN)?;
+        let lease = state
+            .db
+            .create_lease(&link, super::lease::new_token(), ttl)
+            .await
+            .to_status()?;
+        let headers = response.headers_mut();
+        headers.insert(
+            super::lease::LEASE_HEADER,
+            HeaderValue::from_str(&lease.lease).to_status()?,
+        );
+        headers.insert(
+            super::lease::LEASE_EXPIRES_HEADER,
+            HeaderValue::from_str(&lease.expires_at.to_rfc3339()).to_status()?,
+        );
+    }
+    Ok(response)
 }
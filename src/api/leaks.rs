@@ -0,0 +1,98 @@
+use axum::{Json, extract::State, http::StatusCode};
+
+use crate::database::{audit::AuditActor, keyfields::FieldKind, keys::KeyKind};
+use crate::leaks::is_leaked;
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LeakedKey {
+    pub id: i64,
+    /// What was found: `secret`, `rotate_with`, or `field:<name>`
+    pub found: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LeakedClientSecret {
+    pub client: i64,
+    /// The key of the link the secret belongs to
+    pub key: i64,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LeakReport {
+    pub keys: Vec<LeakedKey>,
+    pub clients: Vec<LeakedClientSecret>,
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/leaks/check",
+    responses(
+        (status = OK, body = inline(LeakReport), description = "The keys and client secrets of the org found in the text (never their values)"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    request_body(content = String, content_type = "text/plain")
+)]
+pub async fn leaks_check(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    text: String,
+) -> Result<Json<LeakReport>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let org = user.org();
+    let actor = AuditActor::User(user.get_id().await);
+    let candidates = crate::leaks::candidates(&text);
+    let mut report = LeakReport::default();
+
+    for key in db.get_all_keys(org).await.to_status()? {
+        let id = key.id.inner();
+        let mut leaked = Vec::new();
+        let mut found = |what: String| leaked.push(what);
+        if key
+            .key
+            .as_deref()
+            .is_some_and(|v| is_leaked(&candidates, v))
+        {
+            found("secret".to_string());
+        }
+        if key
+            .rotate_with
+            .as_deref()
+            .is_some_and(|v| is_leaked(&candidates, v))
+        {
+            found("rotate_with".to_string());
+        }
+        if key.kind == KeyKind::Fields {
+            for field in db.get_all_key_fields(key.id).await.to_status()? {
+                if matches!(field.kind, FieldKind::Secret | FieldKind::Url)
+                    && is_leaked(&candidates, &field.value)
+                {
+                    found(format!("field:{}", field.name));
+                }
+            }
+        }
+        for what in leaked {
+            crate::honeytoken::check(
+                &state,
+                &key,
+                actor,
+                crate::honeytoken::Access::Leaked(what.clone()),
+                ip,
+            )
+            .await;
+            report.keys.push(LeakedKey { id, found: what });
+        }
+    }
+
+    for link in db.get_all_client_keys(org).await.to_status()? {
+        if is_leaked(&candidates, &link.secret) {
+            report.clients.push(LeakedClientSecret {
+                client: link.client_id.inner(),
+                key: link.key_id.inner(),
+            });
+        }
+    }
+
+    Ok(Json(report))
+}
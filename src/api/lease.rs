@@ -0,0 +1,200 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use tracing::{error, warn};
+
+use crate::database::leases::TableLeases;
+use crate::generator::random_token;
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+/// Response headers of `get_key` for a leased key
+pub const LEASE_HEADER: &str = "x-akm-lease";
+pub const LEASE_EXPIRES_HEADER: &str = "x-akm-lease-expires";
+
+/// Prefix of the lease handed to clients, so it isn't mistaken for a secret
+pub const PREFIX: &str = "akl_";
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LeaseInfo {
+    pub id: i64,
+    pub client: i64,
+    /// The link the lease was granted through
+    pub link: i64,
+    pub granted_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Whether the client still holds a live copy of the key
+    pub live: bool,
+}
+
+impl From<TableLeases> for LeaseInfo {
+    fn from(v: TableLeases) -> Self {
+        Self {
+            live: v.is_live(Utc::now()),
+            id: v.id.inner(),
+            client: v.client_id.inner(),
+            link: v.link_id.inner(),
+            granted_at: v.granted_at,
+            expires_at: v.expires_at,
+            revoked_at: v.revoked_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LeaseTtl {
+    /// Seconds a client has to renew its lease, none to stop granting leases
+    #[serde(default)]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LeaseRenewal {
+    pub lease: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub fn new_token() -> String {
+    random_token(PREFIX, TOKEN_LENGTH)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/key/{key}/lease_ttl",
+    responses(
+        (status = OK, description = "Retrievals of the key now grant leases of this TTL, or none"),
+        (status = BAD_REQUEST, description = "The TTL is zero"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+    request_body(content = inline(LeaseTtl), content_type = "application/json")
+)]
+pub async fn key_set_lease_ttl(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+    Json(LeaseTtl { ttl }): Json<LeaseTtl>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    if ttl == Some(0) {
+        error!("lease TTL must be at least a second");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.update_key_lease_ttl(key.id, ttl.map(i64::from))
+        .await
+        .to_status()
+        .map(|_| StatusCode::OK)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/leases",
+    responses(
+        (status = OK, body = inline(Vec<LeaseInfo>), description = "Every lease of the key, latest first"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_leases(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<Vec<LeaseInfo>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.get_all_leases_of_key(key.id)
+        .await
+        .to_status()
+        .map(|v| v.into_iter().map(LeaseInfo::from).collect())
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(delete, path = "/key/{key}/leases",
+    responses(
+        (status = OK, body = u64, description = "How many live leases were revoked, their clients learn it on their next renewal"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_revoke_leases(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<u64>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.revoke_key_leases(key.id).await.to_status().map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/lease/renew",
+    responses(
+        (status = OK, body = inline(LeaseRenewal), description = "The lease was pushed back by its TTL"),
+        (status = NOT_FOUND, description = "No such lease granted through this link"),
+        (status = GONE, description = "The lease was revoked (the key was rotated, or its leases revoked) or it expired: discard the copy of the key, and get it again"),
+        (status = FORBIDDEN, description = "Invalid client secret (`Authorization: Bearer <secret>`), or the link is outside of its validity or used up"),
+    ),
+    request_body(content = String, description = "The lease, from the `X-Akm-Lease` header of the retrieval", content_type = "text/plain")
+)]
+pub async fn lease_renew(
+    client: crate::auth::ClientAuth,
+    State(state): State<crate::AppState>,
+    lease: String,
+) -> Result<Json<LeaseRenewal>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let lease = db
+        .fetch_lease(client.link(), lease.trim())
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let renewed = db.renew_lease(&lease).await.to_status()?;
+    if !renewed {
+        warn!(
+            "client {} can't renew lease {} of key {} anymore",
+            client.client().inner(),
+            lease.id.inner(),
+            lease.key_id.inner()
+        );
+        return Err(StatusCode::GONE);
+    }
+
+    db.fetch_lease(client.link(), &lease.lease)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::GONE)
+        .map(|l| {
+            Json(LeaseRenewal {
+                lease: l.lease,
+                expires_at: l.expires_at,
+            })
+        })
+}
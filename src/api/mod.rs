@@ -2,7 +2,17 @@ use axum::http::StatusCode;
 
 pub mod client;
 pub mod key;
+pub mod leaks;
+pub mod lease;
+pub mod org;
+pub mod secret;
+pub mod share;
+pub mod signing;
+pub mod token;
+pub mod totp;
+pub mod transit;
 pub mod utils;
+pub mod wrap;
 
 pub trait ErrorToStatusCode<T> {
     fn to_status(self) -> std::result::Result<T, StatusCode>;
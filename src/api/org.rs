@@ -0,0 +1,196 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use tracing::error;
+
+use super::ErrorToStatusCode;
+use crate::{database::users::UserId, state::AppState};
+
+#[derive(serde::Serialize, Clone, Debug, utoipa::ToSchema)]
+pub struct OrgInfo {
+    pub id: i64,
+    pub name: String,
+    /// The org the user is currently working in
+    pub current: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, utoipa::ToSchema)]
+pub struct NewOrgInfo {
+    pub name: String,
+}
+
+#[derive(serde::Serialize, Clone, Debug, utoipa::ToSchema)]
+pub struct MemberInfo {
+    pub id: i64,
+    pub name: String,
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/org/list", 
+    responses(
+        (status = OK, body = inline(Vec<OrgInfo>), description = "Every org the user is a member of"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+)]
+pub async fn org_list(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+) -> Result<Json<Vec<OrgInfo>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    db.get_all_orgs_from_user(user.get_id().await)
+        .await
+        .to_status()
+        .map(|v| {
+            v.into_iter().map(|o| OrgInfo {
+                current: o.id == user.org(),
+                id: o.id.inner(),
+                name: o.name,
+            })
+        })
+        .map(Iterator::collect)
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/org/new", 
+    responses(
+        (status = OK, body = i64, description = "Org was created, the user is its first member"),
+        (status = BAD_REQUEST, description = "Invalid Request: name must be between 1 and 256 characters"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = CONFLICT, description = "An org with this name already exists"),
+    ),
+    request_body(content = inline(NewOrgInfo), content_type = "application/json")
+)]
+pub async fn org_new(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Json(info): Json<NewOrgInfo>,
+) -> Result<Json<i64>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    if !(1..=256).contains(&info.name.trim().chars().count()) {
+        error!("new org name must be between 1 and 256 characters");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let org = db
+        .create_org(info.name.trim())
+        .await
+        .to_status()?
+        .ok_or(StatusCode::CONFLICT)?;
+    db.add_org_member(org, user.get_id().await)
+        .await
+        .to_status()?;
+    Ok(Json(org.inner()))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/org/{org}/members", 
+    responses(
+        (status = OK, body = inline(Vec<MemberInfo>), description = "Members of the org"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The org doesn't exist or the user isn't a member of it"),
+    ),
+    params(
+        ("org" = i64, Path, description = "The org"),
+    ),
+)]
+pub async fn org_members(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path(org): Path<i64>,
+) -> Result<Json<Vec<MemberInfo>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let org = super::utils::org_from_raw(db, user.get_id().await, org)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.get_all_org_members(org.id)
+        .await
+        .to_status()
+        .map(|v| {
+            v.into_iter().map(|u| MemberInfo {
+                id: u.id.inner(),
+                name: u.name,
+            })
+        })
+        .map(Iterator::collect)
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/org/{org}/member/{name}", 
+    responses(
+        (status = OK, description = "The user is now a member of the org"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The org doesn't exist, the user isn't a member of it, or no user with this name has ever logged in"),
+        (status = CONFLICT, description = "The user is already a member of the org"),
+    ),
+    params(
+        ("org" = i64, Path, description = "The org"),
+        ("name" = String, Path, description = "The name of the user to add"),
+    ),
+)]
+pub async fn org_add_member(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((org, name)): Path<(i64, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let org = super::utils::org_from_raw(db, user.get_id().await, org)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let member = db
+        .get_user_from_name(&name)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if db.add_org_member(org.id, member.id).await.to_status()? {
+        Ok(StatusCode::OK)
+    } else {
+        Err(StatusCode::CONFLICT)
+    }
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(delete, path = "/org/{org}/member/{user}/delete", 
+    responses(
+        (status = OK, description = "The user isn't a member of the org anymore"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The org doesn't exist, the user isn't a member of it, or the member doesn't exist"),
+    ),
+    params(
+        ("org" = i64, Path, description = "The org"),
+        ("user" = i64, Path, description = "The member to remove"),
+    ),
+)]
+pub async fn org_remove_member(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((org, member)): Path<(i64, i64)>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let org = super::utils::org_from_raw(db, user.get_id().await, org)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let member = UserId::from_raw(db, member)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if db.remove_org_member(org.id, member).await.to_status()? {
+        Ok(StatusCode::OK)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,61 @@
+use axum::{Json, extract::State, http::StatusCode};
+use std::collections::BTreeMap;
+use tracing::error;
+
+use crate::generator::SecretPolicy;
+use crate::state::AppState;
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct GenerateRequest {
+    /// `default` when missing
+    #[serde(default = "default_policy")]
+    pub policy: String,
+}
+
+fn default_policy() -> String {
+    crate::generator::DEFAULT_POLICY.to_string()
+}
+
+/// Generate a secret with the named policy of the config
+pub(super) fn generate(state: &AppState, policy: &str) -> Result<String, StatusCode> {
+    state
+        .config
+        .secret_policies
+        .get(policy)
+        .map(SecretPolicy::generate)
+        .ok_or_else(|| {
+            error!("unknown secret policy: {policy}");
+            StatusCode::BAD_REQUEST
+        })
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/secret/policies",
+    responses(
+        (status = OK, body = inline(BTreeMap<String, SecretPolicy>), description = "Every policy secrets can be generated with"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+)]
+pub async fn secret_policies(
+    _user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+) -> Json<BTreeMap<String, SecretPolicy>> {
+    Json(state.config.secret_policies.clone())
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/secret/generate",
+    responses(
+        (status = OK, body = String, description = "A new secret, it isn't stored anywhere"),
+        (status = BAD_REQUEST, description = "Invalid Request: unknown policy"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    request_body(content = inline(GenerateRequest), content_type = "application/json")
+)]
+pub async fn secret_generate(
+    _user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Json(request): Json<GenerateRequest>,
+) -> Result<Json<String>, StatusCode> {
+    generate(&state, &request.policy).map(Json)
+}
@@ -0,0 +1,318 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tracing::{error, warn};
+
+use crate::database::audit::{AuditActor, AuditSeverity};
+use crate::database::keys::KeyKind;
+use crate::database::shares::TableShares;
+use crate::ratelimit::Credential;
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+const AUDIT_CREATED: &str = "share.created";
+const AUDIT_REVOKED: &str = "share.revoked";
+const AUDIT_OPENED: &str = "share.opened";
+const AUDIT_GONE: &str = "share.gone";
+const AUDIT_WRONG_PASSPHRASE: &str = "share.wrong_passphrase";
+const AUDIT_BURNED: &str = "share.burned";
+
+/// Links expire after a day unless told otherwise, and never last more than a week
+const DEFAULT_EXPIRES_IN_SECS: u64 = 24 * 60 * 60;
+const MAX_EXPIRES_IN_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct NewShare {
+    /// Seconds the link can be opened for, a day when missing and a week at most
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    /// Asked to whoever opens the link
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ShareInfo {
+    pub id: i64,
+    /// The user who created the link
+    pub user: i64,
+    /// Only returned on creation, to append to the address of akm
+    pub link: Option<String>,
+    pub protected: bool,
+    pub failed_attempts: i64,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Set once opened, revoked, or out of passphrase attempts
+    pub burned_at: Option<DateTime<Utc>>,
+    /// Whether the link can still be opened
+    pub open: bool,
+}
+
+impl From<TableShares> for ShareInfo {
+    fn from(v: TableShares) -> Self {
+        Self {
+            open: v.is_open(Utc::now()),
+            id: v.id.inner(),
+            user: v.user_id.inner(),
+            link: None,
+            protected: v.passphrase.is_some(),
+            failed_attempts: v.failed_attempts,
+            created_at: v.created_at,
+            expires_at: v.expires_at,
+            burned_at: v.burned_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct OpenShare {
+    /// From the fragment of the link
+    pub token: String,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+async fn audit(state: &AppState, share: &TableShares, actor: AuditActor, action: &str) {
+    if let Err(e) = state
+        .db
+        .log_audit(
+            share.org,
+            Some(share.key_id),
+            actor,
+            action,
+            AuditSeverity::Info,
+        )
+        .await
+    {
+        error!("Failed to audit share {}: {e}", share.id.inner());
+    }
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/key/{key}/share",
+    responses(
+        (status = OK, body = inline(ShareInfo), description = "A link revealing the value of the key once, the only time it is returned"),
+        (status = BAD_REQUEST, description = "The key is a managed (signing, transit or totp) key, the passphrase is empty, or the expiry is zero or over a week"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+    request_body(content = inline(NewShare), content_type = "application/json")
+)]
+pub async fn key_share(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+    Json(request): Json<NewShare>,
+) -> Result<Json<ShareInfo>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let expires_in = request.expires_in.unwrap_or(DEFAULT_EXPIRES_IN_SECS);
+    if !(1..=MAX_EXPIRES_IN_SECS).contains(&expires_in) {
+        error!("share links last between a second and a week, not {expires_in}s");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.passphrase.as_deref() == Some("") {
+        error!("empty share passphrase");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let org = user.org();
+    let key = super::utils::key_from_raw(db, org, key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if matches!(
+        key.kind,
+        KeyKind::Signing | KeyKind::Transit | KeyKind::Totp
+    ) {
+        error!("managed keys never leave akm");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // hashing is slow on purpose, keep it off the runtime
+    let passphrase = match request.passphrase {
+        Some(p) => Some(
+            tokio::task::spawn_blocking(move || crate::share::hash_passphrase(&p))
+                .await
+                .to_status()?,
+        ),
+        None => None,
+    };
+    let token = crate::share::new_token();
+    let expires_at = Utc::now() + chrono::TimeDelta::seconds(expires_in as i64);
+    let user = user.get_id().await;
+    let share = db
+        .create_share(org, key.id, user, &token, passphrase, expires_at)
+        .await
+        .to_status()?;
+    audit(&state, &share, AuditActor::User(user), AUDIT_CREATED).await;
+
+    Ok(Json(ShareInfo {
+        link: Some(crate::share::link(&token)),
+        ..share.into()
+    }))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/shares",
+    responses(
+        (status = OK, body = inline(Vec<ShareInfo>), description = "Every share link of the key, latest first"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_shares(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<Vec<ShareInfo>>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.get_all_shares_of_key(key.id)
+        .await
+        .to_status()
+        .map(|v| v.into_iter().map(ShareInfo::from).collect())
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(delete, path = "/key/{key}/share/{share}",
+    responses(
+        (status = OK, description = "The link can't be opened anymore"),
+        (status = NOT_FOUND, description = "Key or share link not found"),
+        (status = GONE, description = "The link was already opened, revoked or expired"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+        ("share" = i64, Path, description = "The share link"),
+    ),
+)]
+pub async fn key_revoke_share(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key, share)): Path<(i64, i64)>,
+) -> Result<StatusCode, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let share = db
+        .get_all_shares_of_key(key.id)
+        .await
+        .to_status()?
+        .into_iter()
+        .find(|s| s.id.inner() == share)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !db.burn_share(share.id).await.to_status()? {
+        return Err(StatusCode::GONE);
+    }
+    audit(
+        &state,
+        &share,
+        AuditActor::User(user.get_id().await),
+        AUDIT_REVOKED,
+    )
+    .await;
+    Ok(StatusCode::OK)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/share/open",
+    responses(
+        (status = OK, body = String, description = "The key secret, its fields as `NAME='value'` lines, its raw file, or its SSH private key. The link is burned"),
+        (status = NOT_FOUND, description = "No such share link"),
+        (status = GONE, description = "The link was already opened, revoked or expired"),
+        (status = FORBIDDEN, description = "Wrong or missing passphrase, the link burns after a few"),
+        (status = TOO_MANY_REQUESTS, description = "Too many requests from this address or with this link, or locked out after unknown links and wrong passphrases, see `Retry-After`"),
+    ),
+    request_body(content = inline(OpenShare), content_type = "application/json")
+)]
+pub async fn share_open(
+    State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    Json(request): Json<OpenShare>,
+) -> Result<Response, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    if let Err(limited) = state.limiter.check(ip) {
+        return Ok(limited.into_response());
+    }
+    let Some(share) = db
+        .fetch_share_by_token(request.token.trim())
+        .await
+        .to_status()?
+    else {
+        state.limiter.failure(ip);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    // passphrases guessed from many addresses still share a limit
+    if let Err(limited) = state
+        .limiter
+        .check_credential(Credential::Share(share.id.inner()))
+    {
+        return Ok(limited.into_response());
+    }
+    let actor = AuditActor::Share(share.id);
+    if !share.is_open(Utc::now()) {
+        audit(&state, &share, actor, AUDIT_GONE).await;
+        return Err(StatusCode::GONE);
+    }
+
+    if let Some(stored) = share.passphrase.clone() {
+        let given = request.passphrase.unwrap_or_default();
+        let matches =
+            tokio::task::spawn_blocking(move || crate::share::verify_passphrase(&stored, &given))
+                .await
+                .to_status()?;
+        if !matches {
+            state.limiter.failure(ip);
+            audit(&state, &share, actor, AUDIT_WRONG_PASSPHRASE).await;
+            let failed = db.record_share_failure(share.id).await.to_status()?;
+            if failed >= crate::share::MAX_FAILED_ATTEMPTS {
+                warn!(
+                    "share {} burned after {failed} wrong passphrases",
+                    share.id.inner()
+                );
+                db.burn_share(share.id).await.to_status()?;
+                audit(&state, &share, actor, AUDIT_BURNED).await;
+            }
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    // burned before revealing anything, so concurrent openings can't both get the value
+    if !db.burn_share(share.id).await.to_status()? {
+        audit(&state, &share, actor, AUDIT_GONE).await;
+        return Err(StatusCode::GONE);
+    }
+    let key = db
+        .fetch_key_by_id(share.key_id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    crate::honeytoken::check(&state, &key, actor, crate::honeytoken::Access::Shared, ip).await;
+    let mut response = super::key::key_value(&state, &key, super::key::KeyFormat::Env).await?;
+    audit(&state, &share, actor, AUDIT_OPENED).await;
+
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok(response)
+}
@@ -0,0 +1,131 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use std::str::FromStr;
+use tracing::error;
+
+use crate::database::keys::{KeyId, KeyKind};
+use crate::signing::SigningAlgorithm;
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct Jwks {
+    /// Public JWKs, current version first
+    #[schema(value_type = Vec<Object>)]
+    pub keys: Vec<serde_json::Value>,
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/.well-known/{jwks}/jwks.json", 
+    responses(
+        (status = OK, body = inline(Jwks), description = "Public halves of the current and still published versions of the signing key"),
+        (status = NOT_FOUND, description = "No signing key with this JWKS id"),
+    ),
+    params(
+        ("jwks" = String, Path, description = "The JWKS id of the signing key, as in its `jwks_id`"),
+    ),
+)]
+pub async fn key_jwks(
+    State(state): State<crate::AppState>,
+    Path((jwks_id,)): Path<(String,)>,
+) -> Result<Json<Jwks>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = db
+        .fetch_key_by_jwks_id(&jwks_id)
+        .await
+        .to_status()?
+        .filter(|k| k.kind == KeyKind::Signing)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut keys = Vec::new();
+    for version in db
+        .get_live_key_versions(key.id, crate::rotation::today())
+        .await
+        .to_status()?
+    {
+        let jwk = SigningAlgorithm::from_str(&version.algorithm)
+            .and_then(|alg| {
+                alg.public_jwk(&version.material, &crate::signing::kid(version.version))
+            })
+            .inspect_err(|e| {
+                error!(
+                    "invalid version {} of signing key {}: {e}",
+                    version.version,
+                    key.id.inner()
+                )
+            })
+            .to_status()?;
+        keys.push(jwk);
+    }
+    Ok(Json(Jwks { keys }))
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SignRequest {
+    /// Base64 of the bytes to sign
+    pub payload: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SignResponse {
+    /// The version of the key used, as published in the JWKS
+    pub kid: String,
+    pub alg: SigningAlgorithm,
+    /// Base64 of the signature
+    pub signature: String,
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/sign", 
+    responses(
+        (status = OK, body = inline(SignResponse), description = "The payload signed with the current version of the key"),
+        (status = BAD_REQUEST, description = "Invalid Request: the payload isn't base64, or the linked key isn't a signing key"),
+        (status = FORBIDDEN, description = "Invalid client secret (`Authorization: Bearer <secret>`), or the link is outside of its validity or used up"),
+    ),
+    request_body(content = inline(SignRequest), content_type = "application/json"),
+)]
+pub async fn sign(
+    client: crate::auth::ClientAuth,
+    State(state): State<crate::AppState>,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignResponse>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = db
+        .fetch_linked_key(client.link())
+        .await
+        .to_status()?
+        .ok_or(StatusCode::FORBIDDEN)?;
+    if key.kind != KeyKind::Signing {
+        error!("key {} isn't a signing key", key.id.inner());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let payload = STANDARD.decode(&request.payload).map_err(|e| {
+        error!("sign payload isn't base64: {e}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let version = db
+        .fetch_current_key_version(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let alg = SigningAlgorithm::from_str(&version.algorithm).to_status()?;
+    let signature = alg.sign(&version.material, &payload).to_status()?;
+
+    // the signature isn't handed out once the link is used up
+    if !db.record_client_key_use(client.link()).await.to_status()? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(SignResponse {
+        kid: crate::signing::kid(version.version),
+        alg,
+        signature: STANDARD.encode(signature),
+    }))
+}
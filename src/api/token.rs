@@ -0,0 +1,110 @@
+use axum::{
+    Form, Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+
+use crate::accesstoken::Claims;
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+/// OAuth2 client credentials grant, with the secret of any link of the client
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TokenRequest {
+    /// Must be `client_credentials`
+    pub grant_type: String,
+    pub client_secret: String,
+    /// Space separated ids of the keys the token is for, every key linked to the client when missing
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    /// Always `Bearer`
+    pub token_type: String,
+    /// Seconds
+    pub expires_in: u64,
+    /// Space separated ids of the keys the token is for
+    pub scope: String,
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/client/token",
+    responses(
+        (status = OK, body = inline(TokenResponse), description = "A token to use as `Authorization: Bearer <token>` (with `X-Akm-Key` when it is for more than one key) in place of the secret"),
+        (status = BAD_REQUEST, description = "Unsupported grant type, or the scope holds keys not linked to the client"),
+        (status = FORBIDDEN, description = "Invalid client secret, or used from outside of the allowed IP ranges"),
+        (status = TOO_MANY_REQUESTS, description = "Too many requests from this address or with this secret, or locked out after unknown secrets, see `Retry-After`"),
+    ),
+    request_body(content = inline(TokenRequest), content_type = "application/x-www-form-urlencoded")
+)]
+pub async fn client_token(
+    State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    Form(request): Form<TokenRequest>,
+) -> Result<Response, StatusCode> {
+    if request.grant_type != "client_credentials" {
+        error!("unsupported grant type: {}", request.grant_type);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let client =
+        match crate::auth::ClientAuth::from_secret(&state, &request.client_secret, ip).await {
+            Ok(client) => client.client(),
+            Err(response) => return Ok(response),
+        };
+    let AppState { ref db, .. } = state;
+
+    let client = db
+        .fetch_client_by_id(client)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::FORBIDDEN)?;
+    let linked = db
+        .get_all_keys_from_client(client.org, client.id)
+        .await
+        .to_status()?
+        .into_iter()
+        .map(|k| k.id.inner())
+        .collect::<Vec<_>>();
+
+    let keys = match request.scope.as_deref().map(str::split_whitespace) {
+        None => linked,
+        Some(scope) => {
+            let keys = scope
+                .map(str::parse)
+                .collect::<Result<Vec<i64>, _>>()
+                .map_err(|e| {
+                    error!("token scope isn't a list of key ids: {e}");
+                    StatusCode::BAD_REQUEST
+                })?;
+            if let Some(key) = keys.iter().find(|k| !linked.contains(k)) {
+                error!("key {key} isn't linked to client {}", client.id.inner());
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            keys
+        }
+    };
+
+    let lifetime = state.config.token_lifetime_secs;
+    let claims = Claims {
+        client: client.id.inner(),
+        generation: client.token_generation,
+        keys,
+        expires: chrono::Utc::now().timestamp() + lifetime as i64,
+    };
+    Ok(Json(TokenResponse {
+        access_token: crate::accesstoken::issue(&state.token_key, &claims),
+        token_type: "Bearer".to_string(),
+        expires_in: lifetime,
+        scope: claims
+            .keys
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(" "),
+    })
+    .into_response())
+}
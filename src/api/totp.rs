@@ -0,0 +1,211 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use tracing::error;
+
+use crate::database::{
+    audit::{AuditActor, AuditSeverity},
+    keys::{KeyKind, TableKeys},
+};
+use crate::totp::{TotpCode, TotpSeed};
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+/// Action recorded in the audit log for every code handed out
+const AUDIT_CODE: &str = "totp.code";
+
+/// Either an `otpauth://` URI, or the seed itself
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(untagged)]
+pub enum TotpImport {
+    Uri { uri: String },
+    Seed(TotpSeed),
+}
+
+/// What can be shown about a TOTP, without its secret
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TotpInfo {
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+    pub algorithm: crate::totp::TotpAlgorithm,
+    pub digits: u32,
+    pub period: u32,
+}
+
+impl From<TotpSeed> for TotpInfo {
+    fn from(seed: TotpSeed) -> Self {
+        Self {
+            issuer: seed.issuer,
+            account: seed.account,
+            algorithm: seed.algorithm,
+            digits: seed.digits,
+            period: seed.period,
+        }
+    }
+}
+
+// the current code of a totp key, audited
+async fn audited_code(
+    state: &AppState,
+    key: &TableKeys,
+    actor: AuditActor,
+) -> Result<TotpCode, StatusCode> {
+    if key.kind != KeyKind::Totp {
+        error!("key {} isn't a totp key", key.id.inner());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let seed = state
+        .db
+        .fetch_key_totp(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?
+        .seed;
+    let code = seed.current_code().to_status()?;
+    state
+        .db
+        .log_audit(
+            key.org,
+            Some(key.id),
+            actor,
+            AUDIT_CODE,
+            AuditSeverity::Info,
+        )
+        .await
+        .to_status()?;
+    Ok(code)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(put, path = "/key/{key}/totp", 
+    responses(
+        (status = OK, body = inline(TotpInfo), description = "Seed imported, replacing the previous one"),
+        (status = BAD_REQUEST, description = "Invalid Request: the key isn't a totp key, or the URI/seed is invalid"),
+        (status = NOT_FOUND, description = "Key not found"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+    request_body(content = inline(TotpImport), content_type = "application/json")
+)]
+pub async fn key_set_totp(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+    Json(import): Json<TotpImport>,
+) -> Result<Json<TotpInfo>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if key.kind != KeyKind::Totp {
+        error!("key {} isn't a totp key", key.id.inner());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let seed = match import {
+        TotpImport::Uri { uri } => TotpSeed::from_uri(&uri),
+        TotpImport::Seed(seed) => seed.validate().map(|_| seed),
+    }
+    .map_err(|e| {
+        error!("invalid totp seed: {e}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    db.set_key_totp(key.id, &seed).await.to_status()?;
+    Ok(Json(seed.into()))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/totp", 
+    responses(
+        (status = OK, body = inline(TotpInfo), description = "The TOTP parameters (no secret)"),
+        (status = NOT_FOUND, description = "Key not found, or no seed imported yet"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_totp_info(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<TotpInfo>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let key = super::utils::key_from_raw(db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    db.fetch_key_totp(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)
+        .map(|t| Json(t.seed.into()))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(get, path = "/key/{key}/totp/code", 
+    responses(
+        (status = OK, body = inline(TotpCode), description = "The current code, the request is audited"),
+        (status = BAD_REQUEST, description = "The key isn't a totp key"),
+        (status = NOT_FOUND, description = "Key not found, or no seed imported yet"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+    ),
+    params(
+        ("key" = i64, Path, description = "The key"),
+    ),
+)]
+pub async fn key_totp_code(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((key,)): Path<(i64,)>,
+) -> Result<Json<TotpCode>, StatusCode> {
+    let key = super::utils::key_from_raw(&state.db, user.org(), key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    audited_code(&state, &key, AuditActor::User(user.get_id().await))
+        .await
+        .map(Json)
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/totp", 
+    responses(
+        (status = OK, body = inline(TotpCode), description = "The current code of the linked key, the request is audited"),
+        (status = BAD_REQUEST, description = "The linked key isn't a totp key"),
+        (status = NOT_FOUND, description = "No seed imported yet"),
+        (status = FORBIDDEN, description = "Invalid client secret (`Authorization: Bearer <secret>`), or the link is outside of its validity or used up"),
+    ),
+)]
+pub async fn totp_code(
+    client: crate::auth::ClientAuth,
+    State(state): State<crate::AppState>,
+) -> Result<Json<TotpCode>, StatusCode> {
+    let key = state
+        .db
+        .fetch_linked_key(client.link())
+        .await
+        .to_status()?
+        .ok_or(StatusCode::FORBIDDEN)?;
+
+    let code = audited_code(&state, &key, AuditActor::Client(client.client())).await?;
+    // the code isn't handed out once the link is used up
+    if !state
+        .db
+        .record_client_key_use(client.link())
+        .await
+        .to_status()?
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(Json(code))
+}
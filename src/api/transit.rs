@@ -0,0 +1,168 @@
+use axum::{Json, extract::State, http::StatusCode};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use tracing::error;
+
+use crate::database::keys::{KeyKind, TableKeys};
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct EncryptRequest {
+    /// Base64 of the data to encrypt
+    pub plaintext: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct EncryptResponse {
+    /// `akm:v<version>:<base64>`, the version is the one of the key used
+    pub ciphertext: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct DecryptRequest {
+    /// As returned by `/encrypt` or `/rewrap`
+    pub ciphertext: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct DecryptResponse {
+    /// Base64 of the decrypted data
+    pub plaintext: String,
+}
+
+// the transit key linked to the client
+async fn transit_key(
+    state: &AppState,
+    client: &crate::auth::ClientAuth,
+) -> Result<TableKeys, StatusCode> {
+    let key = state
+        .db
+        .fetch_linked_key(client.link())
+        .await
+        .to_status()?
+        .ok_or(StatusCode::FORBIDDEN)?;
+    if key.kind != KeyKind::Transit {
+        error!("key {} isn't a transit key", key.id.inner());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(key)
+}
+
+// the result isn't handed out once the link is used up, and failed requests don't use it
+async fn record_use<T>(
+    state: &AppState,
+    client: &crate::auth::ClientAuth,
+    result: T,
+) -> Result<T, StatusCode> {
+    if !state
+        .db
+        .record_client_key_use(client.link())
+        .await
+        .to_status()?
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(result)
+}
+
+// encrypt with the current version of the key
+async fn encrypt_with_current(
+    state: &AppState,
+    key: &TableKeys,
+    plaintext: &[u8],
+) -> Result<String, StatusCode> {
+    let version = state
+        .db
+        .fetch_current_key_version(key.id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    crate::transit::encrypt(&version.material, version.version, plaintext).to_status()
+}
+
+// decrypt with whichever version of the key the ciphertext was encrypted with
+async fn decrypt_any_version(
+    state: &AppState,
+    key: &TableKeys,
+    ciphertext: &str,
+) -> Result<Vec<u8>, StatusCode> {
+    let Some((version, data)) = crate::transit::parse(ciphertext) else {
+        error!("malformed ciphertext");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let version = state
+        .db
+        .fetch_key_version(key.id, version)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    crate::transit::decrypt(&version.material, &data).map_err(|e| {
+        error!("{e}");
+        StatusCode::BAD_REQUEST
+    })
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/encrypt", 
+    responses(
+        (status = OK, body = inline(EncryptResponse), description = "The data encrypted with the current version of the key"),
+        (status = BAD_REQUEST, description = "Invalid Request: the plaintext isn't base64, or the linked key isn't a transit key"),
+        (status = FORBIDDEN, description = "Invalid client secret (`Authorization: Bearer <secret>`), or the link is outside of its validity or used up"),
+    ),
+    request_body(content = inline(EncryptRequest), content_type = "application/json"),
+)]
+pub async fn encrypt(
+    client: crate::auth::ClientAuth,
+    State(state): State<crate::AppState>,
+    Json(request): Json<EncryptRequest>,
+) -> Result<Json<EncryptResponse>, StatusCode> {
+    let key = transit_key(&state, &client).await?;
+    let plaintext = STANDARD.decode(&request.plaintext).map_err(|e| {
+        error!("plaintext isn't base64: {e}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let ciphertext = encrypt_with_current(&state, &key, &plaintext).await?;
+    record_use(&state, &client, Json(EncryptResponse { ciphertext })).await
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/decrypt", 
+    responses(
+        (status = OK, body = inline(DecryptResponse), description = "The decrypted data"),
+        (status = BAD_REQUEST, description = "Invalid Request: the ciphertext is malformed, was tampered with or wasn't made by this key, or the linked key isn't a transit key"),
+        (status = FORBIDDEN, description = "Invalid client secret (`Authorization: Bearer <secret>`), or the link is outside of its validity or used up"),
+    ),
+    request_body(content = inline(DecryptRequest), content_type = "application/json"),
+)]
+pub async fn decrypt(
+    client: crate::auth::ClientAuth,
+    State(state): State<crate::AppState>,
+    Json(request): Json<DecryptRequest>,
+) -> Result<Json<DecryptResponse>, StatusCode> {
+    let key = transit_key(&state, &client).await?;
+
+    let plaintext = decrypt_any_version(&state, &key, &request.ciphertext).await?;
+    let plaintext = STANDARD.encode(plaintext);
+    record_use(&state, &client, Json(DecryptResponse { plaintext })).await
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/rewrap", 
+    responses(
+        (status = OK, body = inline(EncryptResponse), description = "The same data, encrypted with the current version of the key"),
+        (status = BAD_REQUEST, description = "Invalid Request: the ciphertext is malformed, was tampered with or wasn't made by this key, or the linked key isn't a transit key"),
+        (status = FORBIDDEN, description = "Invalid client secret (`Authorization: Bearer <secret>`), or the link is outside of its validity or used up"),
+    ),
+    request_body(content = inline(DecryptRequest), content_type = "application/json"),
+)]
+pub async fn rewrap(
+    client: crate::auth::ClientAuth,
+    State(state): State<crate::AppState>,
+    Json(request): Json<DecryptRequest>,
+) -> Result<Json<EncryptResponse>, StatusCode> {
+    let key = transit_key(&state, &client).await?;
+
+    let plaintext = decrypt_any_version(&state, &key, &request.ciphertext).await?;
+    let ciphertext = encrypt_with_current(&state, &key, &plaintext).await?;
+    record_use(&state, &client, Json(EncryptResponse { ciphertext })).await
+}
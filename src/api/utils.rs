@@ -1,15 +1,18 @@
 use color_eyre::Result;
 
-use crate::database::{clientkeys::ClientKeyId, clients::ClientId, keys::KeyId, users::UserId};
+use crate::database::{
+    Metadata, clientkeys::ClientKeyId, clients::ClientId, keys::KeyId, orgs::OrgId, users::UserId,
+};
 
 pub async fn client_from_raw(
     db: &crate::database::Database,
+    org: OrgId,
     client: i64,
 ) -> Result<Option<crate::database::clients::TableClients>> {
     let Some(client) = ClientId::from_raw(db, client).await? else {
         return Ok(None);
     };
-    db.fetch_client(client).await
+    db.fetch_client(org, client).await
 }
 
 pub async fn user_from_raw(
@@ -24,26 +27,29 @@ pub async fn user_from_raw(
 
 pub async fn key_from_raw(
     db: &crate::database::Database,
+    org: OrgId,
     key: i64,
 ) -> Result<Option<crate::database::keys::TableKeys>> {
     let Some(key) = KeyId::from_raw(db, key).await? else {
         return Ok(None);
     };
-    db.fetch_key(key).await
+    db.fetch_key(org, key).await
 }
 
 pub async fn clientkey_from_raw(
     db: &crate::database::Database,
+    org: OrgId,
     clientkey: i64,
 ) -> Result<Option<crate::database::clientkeys::TableClientsKey>> {
     let Some(client_key) = ClientKeyId::from_raw(db, clientkey).await? else {
         return Ok(None);
     };
-    db.fetch_client_key(client_key).await
+    db.fetch_client_key(org, client_key).await
 }
 
 pub async fn clientkey_from_client_and_key(
     db: &crate::database::Database,
+    org: OrgId,
     client: i64,
     key: i64,
 ) -> Result<Option<crate::database::clientkeys::TableClientsKey>> {
@@ -53,7 +59,116 @@ pub async fn clientkey_from_client_and_key(
     let Some(key) = KeyId::from_raw(db, key).await? else {
         return Ok(None);
     };
-    db.fetch_client_key_from_client_and_key(client, key).await
+    db.fetch_client_key_from_client_and_key(org, client, key)
+        .await
+}
+
+/// The org, if the user is a member of it
+pub async fn org_from_raw(
+    db: &crate::database::Database,
+    user: UserId,
+    org: i64,
+) -> Result<Option<crate::database::orgs::TableOrgs>> {
+    let Some(org) = OrgId::from_raw(db, org).await? else {
+        return Ok(None);
+    };
+    if !db.is_org_member(org, user).await? {
+        return Ok(None);
+    }
+    db.fetch_org(org).await
+}
+
+/// Names must be alphanumeric or `-`/`_`
+pub fn is_valid_name(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Same as names, but can't be empty
+pub fn is_valid_namespace(namespace: &str) -> bool {
+    !namespace.is_empty() && is_valid_name(namespace)
+}
+
+/// Field names double as environment variable names: `[A-Za-z_][A-Za-z0-9_]*`
+pub fn is_valid_field_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A bare filename: no path separator nor control character, at most 255 bytes
+pub fn is_valid_filename(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && name != "."
+        && name != ".."
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c == '"' || c.is_control())
+}
+
+pub fn default_namespace() -> String {
+    crate::database::DEFAULT_NAMESPACE.to_string()
+}
+
+/// Filter used by the list endpoints (and pages) to search keys and clients
+///
+/// Every field is optional, an empty string is treated as a missing field (html forms send them)
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MetadataFilter {
+    /// Only show this namespace
+    pub namespace: Option<String>,
+    /// Searched in the name, description, owner, contact, provider and tags
+    pub q: Option<String>,
+    /// Must be one of the tags
+    pub tag: Option<String>,
+    pub owner: Option<String>,
+    pub provider: Option<String>,
+    pub environment: Option<String>,
+}
+
+impl MetadataFilter {
+    pub fn matches(&self, namespace: &str, name: &str, desc: &str, metadata: &Metadata) -> bool {
+        fn non_empty(s: &Option<String>) -> Option<String> {
+            s.as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_lowercase)
+        }
+        fn eq(filter: &Option<String>, value: &Option<String>) -> bool {
+            non_empty(filter)
+                .is_none_or(|f| value.as_deref().is_some_and(|v| v.to_lowercase() == f))
+        }
+
+        let q_ok = non_empty(&self.q).is_none_or(|q| {
+            [
+                Some(name),
+                Some(desc),
+                metadata.owner.as_deref(),
+                metadata.contact.as_deref(),
+                metadata.provider.as_deref(),
+            ]
+            .into_iter()
+            .flatten()
+            .chain(metadata.tags.iter().map(String::as_str))
+            .any(|s| s.to_lowercase().contains(&q))
+        });
+        let tag_ok = non_empty(&self.tag)
+            .is_none_or(|t| metadata.tags.iter().any(|tag| tag.to_lowercase() == t));
+
+        let namespace_ok =
+            non_empty(&self.namespace).is_none_or(|ns| namespace.to_lowercase() == ns);
+
+        namespace_ok
+            && q_ok
+            && tag_ok
+            && eq(&self.owner, &metadata.owner)
+            && eq(&self.provider, &metadata.provider)
+            && eq(&self.environment, &metadata.environment)
+    }
 }
 
 // shamelessly stolen from `serde_with`
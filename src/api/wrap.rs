@@ -0,0 +1,196 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tracing::error;
+
+use crate::database::audit::{AuditActor, AuditSeverity};
+use crate::database::wraps::TableWraps;
+use crate::generator::random_token;
+use crate::ratelimit::Credential;
+use crate::{api::ErrorToStatusCode, state::AppState};
+
+/// Prefix of wrapping tokens, so they aren't mistaken for the secret they wrap
+pub const PREFIX: &str = "akw_";
+const TOKEN_LENGTH: usize = 40;
+
+const AUDIT_WRAPPED: &str = "wrap.created";
+const AUDIT_UNWRAPPED: &str = "wrap.unwrapped";
+const AUDIT_REPLAYED: &str = "wrap.replayed";
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct WrappedSecret {
+    /// Unwrapped once on `/wrap/unwrap` for the client secret
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+async fn audit(
+    state: &AppState,
+    wrap: &TableWraps,
+    actor: AuditActor,
+    action: &str,
+    severity: AuditSeverity,
+) {
+    if let Err(e) = state
+        .db
+        .log_audit(wrap.org, Some(wrap.key_id), actor, action, severity)
+        .await
+    {
+        error!("Failed to audit wrap {}: {e}", wrap.id.inner());
+    }
+}
+
+// a second unwrap means someone else got the token, and maybe the secret
+async fn replayed(state: &AppState, wrap: &TableWraps, ip: Option<std::net::IpAddr>) {
+    audit(
+        state,
+        wrap,
+        AuditActor::Client(wrap.client_id),
+        AUDIT_REPLAYED,
+        AuditSeverity::High,
+    )
+    .await;
+    crate::notify::alert(
+        state,
+        crate::notify::Alert {
+            event: AUDIT_REPLAYED,
+            org: wrap.org.inner(),
+            key: Some(wrap.key_id.inner()),
+            client: Some(wrap.client_id.inner()),
+            user: None,
+            ip,
+            message: format!(
+                "wrapped secret of link {} unwrapped again, it was unwrapped from {} at {}: it was likely intercepted, renew the secret",
+                wrap.link_id.inner(),
+                wrap.unwrapped_from
+                    .as_deref()
+                    .unwrap_or("an unknown address"),
+                wrap.unwrapped_at
+                    .map_or_else(|| "the same time".to_string(), |at| at.to_rfc3339()),
+            ),
+            at: Utc::now(),
+        },
+    );
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/client/{client}/key/{key}/wrap",
+    responses(
+        (status = OK, body = inline(WrappedSecret), description = "A short-lived token to hand to the new server in place of the secret, it can be unwrapped only once"),
+        (status = FORBIDDEN, description = "Invalid Auth cookie"),
+        (status = NOT_FOUND, description = "The client or key (or the key<->client relation) doesn't exist"),
+    ),
+    params(
+        ("client" = i64, Path, description = "The client"),
+        ("key" = i64, Path, description = "The key"),
+    ))
+]
+pub async fn client_wrap_secret(
+    user: crate::auth::UserAuth,
+    State(state): State<crate::AppState>,
+    Path((client, key)): Path<(i64, i64)>,
+) -> Result<Json<WrappedSecret>, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let org = user.org();
+    let link = super::utils::clientkey_from_client_and_key(db, org, client, key)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let token = random_token(PREFIX, TOKEN_LENGTH);
+    let expires_at = Utc::now() + chrono::TimeDelta::seconds(state.config.wrap_ttl_secs as i64);
+    let user = user.get_id().await;
+    let wrap = db
+        .create_wrap(org, &link, user, &token, expires_at)
+        .await
+        .to_status()?;
+    audit(
+        &state,
+        &wrap,
+        AuditActor::User(user),
+        AUDIT_WRAPPED,
+        AuditSeverity::Info,
+    )
+    .await;
+
+    Ok(Json(WrappedSecret {
+        token: wrap.token,
+        expires_at: wrap.expires_at,
+    }))
+}
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+#[utoipa::path(post, path = "/wrap/unwrap",
+    responses(
+        (status = OK, body = String, description = "The client secret, the token can't be unwrapped anymore"),
+        (status = NOT_FOUND, description = "No such token"),
+        (status = GONE, description = "The token expired, its link is gone, or it was already unwrapped (which raises an alert)"),
+        (status = TOO_MANY_REQUESTS, description = "Too many requests from this address or with this token, or locked out after unknown tokens, see `Retry-After`"),
+    ),
+    request_body(content = String, description = "The wrapping token", content_type = "text/plain")
+)]
+pub async fn wrap_unwrap(
+    State(state): State<crate::AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    token: String,
+) -> Result<Response, StatusCode> {
+    let AppState { ref db, .. } = state;
+
+    let token = token.trim();
+    if let Err(limited) = state.limiter.check(ip) {
+        return Ok(limited.into_response());
+    }
+    let Some(wrap) = db.fetch_wrap_by_token(token).await.to_status()? else {
+        state.limiter.failure(ip);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if let Err(limited) = state
+        .limiter
+        .check_credential(Credential::Wrap(wrap.id.inner()))
+    {
+        return Ok(limited.into_response());
+    }
+    if wrap.unwrapped_at.is_some() {
+        state.limiter.failure(ip);
+        replayed(&state, &wrap, ip).await;
+        return Err(StatusCode::GONE);
+    }
+
+    // read before the token is burned, so it isn't burned for nothing if the link went away
+    let link = db
+        .fetch_client_key_by_id(wrap.link_id)
+        .await
+        .to_status()?
+        .ok_or(StatusCode::GONE)?;
+    if !db
+        .unwrap_wrap(wrap.id, ip.map(|ip| ip.to_string()))
+        .await
+        .to_status()?
+    {
+        // expired, or unwrapped by a concurrent request since it was fetched
+        let Some(wrap) = db.fetch_wrap_by_token(token).await.to_status()? else {
+            return Err(StatusCode::GONE);
+        };
+        if wrap.unwrapped_at.is_some() {
+            state.limiter.failure(ip);
+            replayed(&state, &wrap, ip).await;
+        }
+        return Err(StatusCode::GONE);
+    }
+
+    audit(
+        &state,
+        &wrap,
+        AuditActor::Client(wrap.client_id),
+        AUDIT_UNWRAPPED,
+        AuditSeverity::Info,
+    )
+    .await;
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], link.secret).into_response())
+}
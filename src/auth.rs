@@ -2,9 +2,9 @@ use std::{borrow::Cow, collections::HashMap};
 
 use axum::{
     Router,
-    extract::{FromRequestParts, OptionalFromRequestParts, Query, State},
+    extract::{FromRequestParts, OptionalFromRequestParts, Path, Query, State},
     http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Redirect},
+    response::{IntoResponse, Redirect, Response},
     routing::get,
 };
 use axum_extra::extract::{
@@ -19,15 +19,60 @@ use openidconnect::{
 use tracing::{debug, error, warn};
 
 use crate::{
-    database::users::{TableUsers, UserId},
+    database::{
+        clientkeys::{ClientKeyId, TableClientsKey},
+        clients::ClientId,
+        keys::KeyId,
+        orgs::OrgId,
+        users::{TableUsers, UserId},
+    },
+    ratelimit::Credential,
     state::AppState,
 };
 
+/// A logged user, and the organisation they are currently working in
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
-pub struct UserAuth(crate::database::users::UserId);
-pub struct UserAuthRedirect(crate::database::users::UserId);
+pub struct UserAuth {
+    user: UserId,
+    org: OrgId,
+}
+pub struct UserAuthRedirect(UserAuth);
 
 const AUTH_COOKIE: &str = "session";
+const ORG_COOKIE: &str = "org";
+
+/// The org selected with the org cookie if the user is still a member of it, otherwise the first
+/// org of the user. A personal org is created for users without any org.
+async fn current_org(
+    db: &crate::database::Database,
+    user: UserId,
+    selected: Option<i64>,
+) -> color_eyre::Result<OrgId> {
+    if let Some(selected) = selected
+        && let Some(org) = OrgId::from_raw(db, selected).await?
+        && db.is_org_member(org, user).await?
+    {
+        return Ok(org);
+    }
+    if let Some(org) = db.get_all_orgs_from_user(user).await?.into_iter().next() {
+        return Ok(org.id);
+    }
+
+    let name = db
+        .fetch_user(user)
+        .await?
+        .wrap_err("user doesn't exist")?
+        .name;
+    let org = match db.create_org(&name).await? {
+        Some(org) => org,
+        None => db
+            .create_org(format!("{name}-{}", user.inner()))
+            .await?
+            .wrap_err("unable to create a personal org")?,
+    };
+    db.add_org_member(org, user).await?;
+    Ok(org)
+}
 
 impl FromRequestParts<AppState> for UserAuth {
     type Rejection = (PrivateCookieJar, StatusCode);
@@ -52,9 +97,18 @@ impl FromRequestParts<AppState> for UserAuth {
                 debug!("Cookie removed");
                 Err((cookies.remove(AUTH_COOKIE), StatusCode::FORBIDDEN))
             }
-            Ok(Some(v)) => {
+            Ok(Some(user)) => {
                 debug!("User is logged");
-                Ok(Self(v))
+                let selected = cookies
+                    .get(ORG_COOKIE)
+                    .and_then(|c| c.value().parse::<i64>().ok());
+                match current_org(&state.db, user, selected).await {
+                    Ok(org) => Ok(Self { user, org }),
+                    Err(e) => {
+                        error!("Failed to get the current org: {e}");
+                        Err((cookies, StatusCode::INTERNAL_SERVER_ERROR))
+                    }
+                }
             }
         }
     }
@@ -84,7 +138,7 @@ impl FromRequestParts<AppState> for UserAuthRedirect {
     ) -> Result<Self, Self::Rejection> {
         <UserAuth as FromRequestParts<AppState>>::from_request_parts(parts, state)
             .await
-            .map(|UserAuth(id)| UserAuthRedirect(id))
+            .map(UserAuthRedirect)
             .map_err(|(c, _)| (c, Redirect::to("/auth/login")))
     }
 }
@@ -98,7 +152,7 @@ impl OptionalFromRequestParts<AppState> for UserAuthRedirect {
     ) -> Result<Option<Self>, Self::Rejection> {
         <UserAuth as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state)
             .await
-            .map(|o| o.map(|UserAuth(id)| UserAuthRedirect(id)))
+            .map(|o| o.map(UserAuthRedirect))
             .map_err(|(c, _)| (c, Redirect::to("/auth/login")))
     }
 }
@@ -108,11 +162,15 @@ impl UserAuthRedirect {
         self,
         db: &crate::database::Database,
     ) -> color_eyre::Result<Option<TableUsers>> {
-        db.fetch_user(self.0).await
+        self.0.get_user(db).await
     }
 
     pub async fn get_id(self) -> UserId {
-        self.0
+        self.0.user
+    }
+
+    pub fn org(&self) -> OrgId {
+        self.0.org
     }
 }
 
@@ -121,19 +179,411 @@ impl UserAuth {
         self,
         db: &crate::database::Database,
     ) -> color_eyre::Result<Option<TableUsers>> {
-        db.fetch_user(self.0).await
+        db.fetch_user(self.user).await
     }
 
     pub async fn get_id(self) -> UserId {
-        self.0
+        self.user
+    }
+
+    pub fn org(&self) -> OrgId {
+        self.org
+    }
+}
+
+/// A client authenticated for one of its links, see [`ClientCredentials`],
+/// it can only use the key of that link
+#[derive(Debug, Clone)]
+pub struct ClientAuth {
+    link: TableClientsKey,
+}
+
+/// Header naming the key a client authenticated with a certificate wants to use,
+/// a secret already says which
+pub const KEY_HEADER: &str = "x-akm-key";
+
+/// What a client proves who it is with
+#[derive(Debug, Clone)]
+pub enum ClientCredentials {
+    /// `Authorization: Bearer <secret>`, or the body of `get_key`
+    Secret(String),
+    /// An access token in place of the secret, see [`crate::accesstoken`], and the key named
+    /// by `X-Akm-Key` (which can be left out when the token is for a single key)
+    Token(String, Option<i64>),
+    /// A request signed with the secret, see [`crate::signedrequest`]
+    Signature(crate::signedrequest::SignedRequest),
+    /// A verified TLS client certificate, and the key named by `X-Akm-Key`
+    Certificate(crate::tls::PeerCertificate, i64),
+}
+
+impl ClientCredentials {
+    /// The credentials in the head of a request, or `secret` sent outside of it.
+    /// `uri` is the one the client sent (`OriginalUri`)
+    pub fn from_head(
+        method: &axum::http::Method,
+        uri: &axum::http::Uri,
+        headers: &HeaderMap,
+        cert: Option<&crate::tls::PeerCertificate>,
+        secret: Option<String>,
+    ) -> Option<Self> {
+        let bearer = headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .map(|s| s.trim().to_string());
+        let key = headers
+            .get(KEY_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.trim().parse().ok());
+        if let Some(secret) = bearer.or(secret) {
+            if crate::accesstoken::is_token(&secret) {
+                return Some(Self::Token(secret, key));
+            }
+            return Some(Self::Secret(secret));
+        }
+        if let Some(request) = crate::signedrequest::SignedRequest::from_head(method, uri, headers)
+        {
+            return Some(Self::Signature(request));
+        }
+        Some(Self::Certificate(cert?.clone(), key?))
+    }
+}
+
+impl FromRequestParts<AppState> for ClientAuth {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let uri = parts
+            .extensions
+            .get::<axum::extract::OriginalUri>()
+            .map_or(&parts.uri, |u| &u.0);
+        let credentials = ClientCredentials::from_head(
+            &parts.method,
+            uri,
+            &parts.headers,
+            parts.extensions.get(),
+            None,
+        );
+        let crate::allowlist::ClientIp(ip) =
+            crate::allowlist::ClientIp::from_request_parts(parts, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+        match credentials {
+            Some(credentials) => Self::from_credentials(state, credentials, ip).await,
+            None => Err(StatusCode::FORBIDDEN.into_response()),
+        }
+    }
+}
+
+impl ClientAuth {
+    pub async fn from_credentials(
+        state: &AppState,
+        credentials: ClientCredentials,
+        ip: Option<std::net::IpAddr>,
+    ) -> Result<Self, Response> {
+        match credentials {
+            ClientCredentials::Secret(secret) => Self::from_secret(state, &secret, ip).await,
+            ClientCredentials::Token(token, key) => Self::from_token(state, &token, key, ip).await,
+            ClientCredentials::Signature(request) => {
+                Self::from_signature(state, &request, ip).await
+            }
+            ClientCredentials::Certificate(cert, key) => {
+                Self::from_certificate(state, &cert, key, ip).await
+            }
+        }
+    }
+
+    /// Resolve a client secret, refusing it outside of the IP ranges of its client and link,
+    /// and once `ip` or the secret went over its rate limit
+    pub async fn from_secret(
+        state: &AppState,
+        secret: &str,
+        ip: Option<std::net::IpAddr>,
+    ) -> Result<Self, Response> {
+        state
+            .limiter
+            .check(ip)
+            .map_err(IntoResponse::into_response)?;
+        if let Some(crate::clientsecret::ParsedSecret::Link(link)) =
+            crate::clientsecret::parse(secret)
+        {
+            state
+                .limiter
+                .check_credential(Credential::Link(link))
+                .map_err(IntoResponse::into_response)?;
+        }
+
+        let link = match state.db.get_client_key_from_secret(secret).await {
+            Err(e) => {
+                error!("Failed to get client key from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(None) => {
+                state.limiter.failure(ip);
+                return Err(StatusCode::FORBIDDEN.into_response());
+            }
+            Ok(Some(link)) => link,
+        };
+
+        let client = match state.db.fetch_client_by_id(link.client_id).await {
+            Err(e) => {
+                error!("Failed to get client from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(client) => client.ok_or(StatusCode::FORBIDDEN.into_response())?,
+        };
+        Self::authorize(state, client, link, ip).await
+    }
+
+    /// Check a request signed with the secret of a link: fresh, and with a nonce the link
+    /// didn't use yet
+    pub async fn from_signature(
+        state: &AppState,
+        request: &crate::signedrequest::SignedRequest,
+        ip: Option<std::net::IpAddr>,
+    ) -> Result<Self, Response> {
+        state
+            .limiter
+            .check(ip)
+            .map_err(IntoResponse::into_response)?;
+        state
+            .limiter
+            .check_credential(Credential::Link(request.link))
+            .map_err(IntoResponse::into_response)?;
+
+        let now = chrono::Utc::now().timestamp();
+        let max_skew = state.config.signature_max_skew_secs;
+        if !request.is_fresh(now, max_skew) {
+            warn!(
+                "signed request for link {} is {}s off",
+                request.link,
+                request.timestamp - now
+            );
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        let link = match ClientKeyId::from_raw(&state.db, request.link).await {
+            Ok(Some(id)) => state.db.fetch_client_key_by_id(id).await,
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let link = match link {
+            Err(e) => {
+                error!("Failed to get client key from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(link) => link.filter(|l| request.verify(&l.secret)),
+        };
+        let Some(link) = link else {
+            state.limiter.failure(ip);
+            return Err(StatusCode::FORBIDDEN.into_response());
+        };
+        // only once the signature is known good, others can't fill the cache
+        if !state.nonces.insert(request, now, max_skew) {
+            warn!("replayed nonce for link {}", request.link);
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        let client = match state.db.fetch_client_by_id(link.client_id).await {
+            Err(e) => {
+                error!("Failed to get client from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(client) => client.ok_or(StatusCode::FORBIDDEN.into_response())?,
+        };
+        Self::authorize(state, client, link, ip).await
+    }
+
+    /// Check an access token, still of the current generation of its client, and that it
+    /// gives access to `key`
+    pub async fn from_token(
+        state: &AppState,
+        token: &str,
+        key: Option<i64>,
+        ip: Option<std::net::IpAddr>,
+    ) -> Result<Self, Response> {
+        state
+            .limiter
+            .check(ip)
+            .map_err(IntoResponse::into_response)?;
+
+        let now = chrono::Utc::now().timestamp();
+        let Some(claims) = crate::accesstoken::verify(&state.token_key, token, now) else {
+            state.limiter.failure(ip);
+            return Err(StatusCode::FORBIDDEN.into_response());
+        };
+        state
+            .limiter
+            .check_credential(Credential::Client(claims.client))
+            .map_err(IntoResponse::into_response)?;
+
+        let client = match ClientId::from_raw(&state.db, claims.client).await {
+            Ok(Some(id)) => state.db.fetch_client_by_id(id).await,
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let client = match client {
+            Err(e) => {
+                error!("Failed to get client from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(client) => client.ok_or(StatusCode::FORBIDDEN.into_response())?,
+        };
+        if client.token_generation != claims.generation {
+            warn!("revoked access token of client {}", claims.client);
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        let key = match (key, claims.keys.as_slice()) {
+            (Some(key), _) => key,
+            (None, [key]) => *key,
+            (None, _) => return Err(StatusCode::FORBIDDEN.into_response()),
+        };
+        if !claims.keys.contains(&key) {
+            warn!(
+                "access token of client {} used for key {key} outside of it",
+                claims.client
+            );
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        let link = match crate::api::utils::clientkey_from_client_and_key(
+            &state.db,
+            client.org,
+            client.id.inner(),
+            key,
+        )
+        .await
+        {
+            Err(e) => {
+                error!("Failed to get client key from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(link) => link.ok_or(StatusCode::FORBIDDEN.into_response())?,
+        };
+        Self::authorize(state, client, link, ip).await
+    }
+
+    /// Resolve the client registered for a (CA verified) certificate among those linked to `key`,
+    /// and its link to it
+    pub async fn from_certificate(
+        state: &AppState,
+        cert: &crate::tls::PeerCertificate,
+        key: i64,
+        ip: Option<std::net::IpAddr>,
+    ) -> Result<Self, Response> {
+        let fingerprint = cert.fingerprint();
+        state
+            .limiter
+            .check(ip)
+            .map_err(IntoResponse::into_response)?;
+        state
+            .limiter
+            .check_credential(Credential::Certificate(fingerprint.clone()))
+            .map_err(IntoResponse::into_response)?;
+
+        let client = match KeyId::from_raw(&state.db, key).await {
+            Ok(Some(key)) => {
+                state
+                    .db
+                    .fetch_client_by_certificate(key, &fingerprint, &cert.sans())
+                    .await
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let client = match client {
+            Err(e) => {
+                error!("Failed to get client from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(None) => {
+                warn!("no client linked to key {key} registered for the certificate {fingerprint}");
+                state.limiter.failure(ip);
+                return Err(StatusCode::FORBIDDEN.into_response());
+            }
+            Ok(Some(client)) => client,
+        };
+
+        let link = match crate::api::utils::clientkey_from_client_and_key(
+            &state.db,
+            client.org,
+            client.id.inner(),
+            key,
+        )
+        .await
+        {
+            Err(e) => {
+                error!("Failed to get client key from db: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Ok(link) => link.ok_or(StatusCode::FORBIDDEN.into_response())?,
+        };
+        Self::authorize(state, client, link, ip).await
+    }
+
+    // the link is only usable within its validity, with uses left, and from the IP ranges
+    // of its client and its own
+    async fn authorize(
+        state: &AppState,
+        client: crate::database::clients::TableClients,
+        link: TableClientsKey,
+        ip: Option<std::net::IpAddr>,
+    ) -> Result<Self, Response> {
+        if !link.is_usable(chrono::Utc::now()) {
+            warn!(
+                "link {} is outside of its validity or used up",
+                link.id.inner()
+            );
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+        if !crate::allowlist::is_allowed(ip, &[&client.allowed_cidrs, &link.allowed_cidrs]) {
+            warn!(
+                "client {} denied the use of key {} from {ip:?}",
+                client.id.inner(),
+                link.key_id.inner()
+            );
+            if let Err(e) = state
+                .db
+                .log_audit(
+                    client.org,
+                    Some(link.key_id),
+                    crate::database::audit::AuditActor::Client(client.id),
+                    "client.denied",
+                    crate::database::audit::AuditSeverity::Info,
+                )
+                .await
+            {
+                error!("Failed to audit a denied client: {e}");
+            }
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        Ok(Self { link })
+    }
+
+    pub fn link(&self) -> ClientKeyId {
+        self.link.id
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.link.client_id
     }
 }
 
 pub(crate) fn router(state: AppState) -> Router {
     Router::new()
-        .route("/logout", get(logout))
         .route("/login", get(oauth2_login))
         .route("/callback", get(oauth2_callback))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::ratelimit::limit_by_ip,
+        ))
+        .route("/logout", get(logout))
+        .route("/org/{org}", get(switch_org))
         .with_state(state)
 }
 
@@ -217,6 +667,35 @@ async fn oauth2_callback(
     }
 }
 
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+async fn switch_org(
+    user: UserAuth,
+    State(state): State<AppState>,
+    Path(org): Path<i64>,
+    jar: PrivateCookieJar,
+) -> Result<(PrivateCookieJar, Redirect), StatusCode> {
+    let Some(org) = OrgId::from_raw(&state.db, org).await.map_err(|e| {
+        error!("Failed to get org: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    match state.db.is_org_member(org, user.user).await {
+        Ok(true) => (),
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to check org membership: {e}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let mut cookie = Cookie::new(ORG_COOKIE, org.inner().to_string());
+    cookie.set_secure(false);
+    cookie.set_path("/");
+    Ok((jar.add(cookie), Redirect::to("/")))
+}
+
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 pub async fn logout(
     State(state): State<AppState>,
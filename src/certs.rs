@@ -0,0 +1,71 @@
+use std::net::IpAddr;
+
+use x509_parser::{extensions::GeneralName, pem::Pem, prelude::X509Certificate};
+
+use crate::database::Date;
+
+/// What we keep from a certificate, to be shown on the key page and to track its expiry
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct CertInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// Subject alternative names (DNS names, IPs, emails and URIs)
+    pub sans: Vec<String>,
+    pub not_after: Date,
+}
+
+impl CertInfo {
+    fn from_x509(cert: &X509Certificate<'_>) -> Option<Self> {
+        let sans = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|ext| {
+                ext.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(s)
+                        | GeneralName::RFC822Name(s)
+                        | GeneralName::URI(s) => Some(s.to_string()),
+                        GeneralName::IPAddress(b) => match b.len() {
+                            4 => Some(IpAddr::from(<[u8; 4]>::try_from(*b).ok()?).to_string()),
+                            16 => Some(IpAddr::from(<[u8; 16]>::try_from(*b).ok()?).to_string()),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let not_after = chrono::DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)?;
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sans,
+            not_after: Date(not_after.date_naive()),
+        })
+    }
+
+    /// Parse the leaf (first) certificate of a PEM chain, or a single DER certificate
+    ///
+    /// Returns `None` when the content isn't a certificate, which is the case of most keys
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        if let Some(pem) = Pem::iter_from_buffer(content)
+            .filter_map(Result::ok)
+            .find(|pem| pem.label == "CERTIFICATE")
+        {
+            return Self::from_x509(&pem.parse_x509().ok()?);
+        }
+        x509_parser::parse_x509_certificate(content)
+            .ok()
+            .and_then(|(_, cert)| Self::from_x509(&cert))
+    }
+
+    /// Day at which the key should be rotated, `days` before the certificate expires
+    pub fn rotate_at(&self, days: u32) -> Date {
+        Date(self.not_after.0 - chrono::Days::new(days.into()))
+    }
+}
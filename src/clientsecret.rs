@@ -0,0 +1,99 @@
+//! Client secrets look like `akm_<link id>_<random>_<crc32>`, so scanners can spot leaked
+//! ones and malformed ones are rejected before looking anything up.
+
+use crate::generator::random_token;
+
+pub const PREFIX: &str = "akm_";
+
+const RANDOM_LENGTH: usize = 40;
+const LEGACY_LENGTH: usize = 64;
+
+/// What a well formed secret tells about its link
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParsedSecret {
+    /// The id of the link (`clients_key`) the secret belongs to
+    Link(i64),
+    /// 64 hex characters, handed out before secrets were structured and valid until rotated
+    Legacy,
+}
+
+pub fn new(link: i64) -> String {
+    let random = random_token("", RANDOM_LENGTH);
+    let body = format!("{PREFIX}{link}_{random}");
+    let crc = crc32fast::hash(body.as_bytes());
+    format!("{body}_{crc:08x}")
+}
+
+pub fn parse(secret: &str) -> Option<ParsedSecret> {
+    if secret.len() == LEGACY_LENGTH
+        && secret
+            .bytes()
+            .all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c))
+    {
+        return Some(ParsedSecret::Legacy);
+    }
+
+    let (body, crc) = secret.rsplit_once('_')?;
+    if crc.len() != 8 || u32::from_str_radix(crc, 16).ok()? != crc32fast::hash(body.as_bytes()) {
+        return None;
+    }
+    let (link, random) = body.strip_prefix(PREFIX)?.split_once('_')?;
+    if random.len() != RANDOM_LENGTH || !random.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    link.parse().ok().map(ParsedSecret::Link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a secret of link `link` made of `random`, with a valid CRC
+    fn with_crc(link: &str, random: &str) -> String {
+        let body = format!("{PREFIX}{link}_{random}");
+        let crc = crc32fast::hash(body.as_bytes());
+        format!("{body}_{crc:08x}")
+    }
+
+    #[test]
+    fn new_secrets_parse_back() {
+        for link in [1, 42, i64::MAX] {
+            assert_eq!(parse(&new(link)), Some(ParsedSecret::Link(link)));
+        }
+    }
+
+    #[test]
+    fn bad_crc_is_rejected() {
+        let secret = new(7);
+        let (body, crc) = secret.rsplit_once('_').unwrap();
+        let flipped = format!("{:08x}", u32::from_str_radix(crc, 16).unwrap() ^ 1);
+        assert_eq!(parse(&format!("{body}_{flipped}")), None);
+        assert_eq!(parse(&format!("{body}_{}", &crc[..7])), None);
+        assert_eq!(parse(body), None);
+    }
+
+    #[test]
+    fn wrong_random_length_is_rejected() {
+        let random = "a".repeat(RANDOM_LENGTH);
+        assert_eq!(parse(&with_crc("7", &random)), Some(ParsedSecret::Link(7)));
+        assert_eq!(parse(&with_crc("7", &random[1..])), None);
+        assert_eq!(parse(&with_crc("7", &format!("{random}a"))), None);
+    }
+
+    #[test]
+    fn non_numeric_link_is_rejected() {
+        let random = "a".repeat(RANDOM_LENGTH);
+        assert_eq!(parse(&with_crc("x7", &random)), None);
+        assert_eq!(parse(&with_crc("", &random)), None);
+        assert_eq!(parse(&with_crc("99999999999999999999", &random)), None);
+    }
+
+    #[test]
+    fn legacy_secrets_are_64_lowercase_hex() {
+        let legacy = "0123456789abcdef".repeat(4);
+        assert_eq!(parse(&legacy), Some(ParsedSecret::Legacy));
+        assert_eq!(parse(&legacy.to_uppercase()), None);
+        assert_eq!(parse(&legacy[1..]), None);
+        assert_eq!(parse(&format!("{}g", &legacy[1..])), None);
+    }
+}
@@ -1,6 +1,8 @@
 use base64::Engine;
 use color_eyre::{Result, eyre::eyre};
-use std::{ffi::OsStr, net::Ipv4Addr, path::PathBuf};
+use std::{collections::BTreeMap, ffi::OsStr, net::Ipv4Addr, path::PathBuf};
+
+use crate::generator::SecretPolicy;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -16,6 +18,53 @@ pub struct Config {
 
     pub template_dir: String,
     pub static_dir: String,
+
+    /// Biggest file a key of kind `file` can hold, in bytes
+    pub max_file_size: usize,
+    /// How many days before expiry a certificate should be rotated, and shows up on the dashboard
+    pub cert_expiry_days: u32,
+    /// How many days the previous versions of a rotated signing key stay published
+    pub signing_grace_days: u32,
+    /// Proxies whose `X-Forwarded-For` is believed, from the comma separated ranges in `TRUSTED_PROXIES`
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    /// Requests a single address, or a single credential, may make per window on the endpoints taking secrets
+    pub rate_limit_requests: u32,
+    pub rate_limit_window_secs: u64,
+    /// Unknown secrets an address may try before being locked out
+    pub lockout_threshold: u32,
+    /// First lockout, doubled on every failure past the threshold
+    pub lockout_base_secs: u64,
+    /// Longest lockout, also how long failures are remembered
+    pub lockout_max_secs: u64,
+    /// How far off the timestamp of a signed request may be, in seconds, either way
+    pub signature_max_skew_secs: u64,
+    /// How long access tokens exchanged from client secrets are valid, in seconds
+    pub token_lifetime_secs: u64,
+    /// How long a wrapped client secret can be unwrapped, in seconds
+    pub wrap_ttl_secs: u64,
+    /// Where security alerts are POSTed as JSON, only logged without it
+    pub alert_webhook: Option<url::Url>,
+    /// PEM certificate chain and key to terminate TLS with, plain HTTP without them
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// PEM CA whose client certificates clients may authenticate with
+    pub tls_client_ca: Option<PathBuf>,
+    /// Named policies to generate secrets with, read from the JSON file in `SECRET_POLICIES`
+    pub secret_policies: BTreeMap<String, SecretPolicy>,
+}
+
+impl Config {
+    const DEFAULT_MAX_FILE_SIZE: usize = 1024 * 1024;
+    const DEFAULT_CERT_EXPIRY_DAYS: u32 = 30;
+    const DEFAULT_SIGNING_GRACE_DAYS: u32 = 7;
+    const DEFAULT_RATE_LIMIT_REQUESTS: u32 = 60;
+    const DEFAULT_RATE_LIMIT_WINDOW_SECS: u64 = 60;
+    const DEFAULT_LOCKOUT_THRESHOLD: u32 = 5;
+    const DEFAULT_LOCKOUT_BASE_SECS: u64 = 2;
+    const DEFAULT_LOCKOUT_MAX_SECS: u64 = 60 * 60;
+    const DEFAULT_SIGNATURE_MAX_SKEW_SECS: u64 = 5 * 60;
+    const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 15 * 60;
+    const DEFAULT_WRAP_TTL_SECS: u64 = 5 * 60;
 }
 
 fn get_var(k: impl AsRef<str>) -> color_eyre::Result<String> {
@@ -45,6 +94,83 @@ impl Config {
 
             template_dir: get_var("TEMPLATE_DIR")?,
             static_dir: get_var("STATIC_DIR")?,
+
+            max_file_size: get_var("MAX_FILE_SIZE")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_MAX_FILE_SIZE),
+            cert_expiry_days: get_var("CERT_EXPIRY_DAYS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_CERT_EXPIRY_DAYS),
+            signing_grace_days: get_var("SIGNING_GRACE_DAYS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_SIGNING_GRACE_DAYS),
+            trusted_proxies: get_var("TRUSTED_PROXIES")
+                .ok()
+                .map(|s| {
+                    s.split(',')
+                        .map(str::trim)
+                        .filter(|r| !r.is_empty())
+                        .map(str::parse)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default(),
+            rate_limit_requests: get_var("RATE_LIMIT_REQUESTS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_RATE_LIMIT_REQUESTS),
+            rate_limit_window_secs: get_var("RATE_LIMIT_WINDOW_SECS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_RATE_LIMIT_WINDOW_SECS),
+            lockout_threshold: get_var("LOCKOUT_THRESHOLD")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_LOCKOUT_THRESHOLD),
+            lockout_base_secs: get_var("LOCKOUT_BASE_SECS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_LOCKOUT_BASE_SECS),
+            lockout_max_secs: get_var("LOCKOUT_MAX_SECS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_LOCKOUT_MAX_SECS),
+            signature_max_skew_secs: get_var("SIGNATURE_MAX_SKEW_SECS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_SIGNATURE_MAX_SKEW_SECS),
+            token_lifetime_secs: get_var("TOKEN_LIFETIME_SECS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_TOKEN_LIFETIME_SECS),
+            wrap_ttl_secs: get_var("WRAP_TTL_SECS")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_WRAP_TTL_SECS),
+            alert_webhook: get_var("ALERT_WEBHOOK")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?,
+            tls_cert: get_var("TLS_CERT").ok().map(PathBuf::from),
+            tls_key: get_var("TLS_KEY").ok().map(PathBuf::from),
+            tls_client_ca: get_var("TLS_CLIENT_CA").ok().map(PathBuf::from),
+            secret_policies: crate::generator::load_policies(
+                get_var("SECRET_POLICIES").ok().as_deref(),
+            )?,
         })
     }
 }
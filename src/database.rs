@@ -5,10 +5,20 @@ use sha2::Digest;
 use sqlx::Executor;
 use std::{path::Path, str::FromStr};
 
+pub mod audit;
 pub mod clientkeys;
 pub mod clients;
+pub mod keycerts;
+pub mod keyfields;
+pub mod keyfiles;
 pub mod keys;
+pub mod keytotp;
+pub mod keyversions;
+pub mod leases;
+pub mod orgs;
+pub mod shares;
 pub mod users;
+pub mod wraps;
 
 #[derive(Clone, Debug)]
 pub struct Database {
@@ -38,18 +48,151 @@ impl std::fmt::Debug for Date {
     }
 }
 
+// RFC 3339 in a fixed format (UTC, whole seconds), so the column compares as text
+pub(crate) fn time_to_db(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+pub(crate) fn time_from_db(time: &str) -> color_eyre::Result<chrono::DateTime<chrono::Utc>> {
+    Ok(chrono::DateTime::parse_from_rfc3339(time)?.to_utc())
+}
+
+/// Namespace used when none is given, this is also the default in `init.sql`
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub struct Metadata {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub contact: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub runbook: Option<String>,
+    #[serde(default)]
+    pub environment: Option<String>,
+}
+
+impl Metadata {
+    pub(crate) fn tags_to_db(&self) -> String {
+        serde_json::to_string(&self.tags).expect("a list of string is always valid json")
+    }
+
+    pub(crate) fn tags_from_db(tags: &str) -> Result<Vec<String>> {
+        serde_json::from_str(tags).map_err(color_eyre::Report::from)
+    }
+
+    /// The runbook is rendered as a link, so it must be an absolute http(s) URL and not some
+    /// `javascript:` one
+    pub fn is_valid_runbook(&self) -> bool {
+        self.runbook.as_deref().is_none_or(|runbook| {
+            url::Url::parse(runbook).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+        })
+    }
+}
+
 impl Database {
     const INIT_SCRIPT: &str = include_str!("./database/init.sql");
+    /// Bring a database created with an older `init.sql` up to date, the `n`th one upgrades from
+    /// `PRAGMA user_version = n`. `init.sql` always creates the latest schema, so append a
+    /// migration here when changing a table that already exists.
+    const MIGRATIONS: &[&str] = &[
+        include_str!("./database/migrations/001-metadata.sql"),
+        include_str!("./database/migrations/002-namespaces.sql"),
+        include_str!("./database/migrations/003-orgs.sql"),
+        include_str!("./database/migrations/004-key-kinds.sql"),
+        include_str!("./database/migrations/005-allowed-cidrs.sql"),
+        include_str!("./database/migrations/006-client-certificates.sql"),
+        include_str!("./database/migrations/007-token-generation.sql"),
+        include_str!("./database/migrations/008-link-limits.sql"),
+        include_str!("./database/migrations/009-lease-ttl.sql"),
+        include_str!("./database/migrations/010-audit-shares.sql"),
+        include_str!("./database/migrations/011-honeytokens.sql"),
+        include_str!("./database/migrations/012-jwks-id.sql"),
+        include_str!("./database/migrations/013-org-certificates.sql"),
+        include_str!("./database/migrations/014-audit-times.sql"),
+    ];
 
     pub async fn new(path: impl AsRef<str>) -> Result<Self> {
         let path = path.as_ref();
         let db = sqlx::SqlitePool::connect(path).await?;
+
+        let mut conn = db.acquire().await?;
+        let fresh =
+            sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'keys'")
+                .fetch_optional(&mut *conn)
+                .await?
+                .is_none();
         {
-            let mut s = db.execute_many(Self::INIT_SCRIPT);
+            let mut s = conn.execute_many(Self::INIT_SCRIPT);
             while s.next().await.transpose()?.is_some() {}
         }
+        if fresh {
+            sqlx::query(&format!("PRAGMA user_version = {}", Self::MIGRATIONS.len()))
+                .execute(&mut *conn)
+                .await?;
+        } else {
+            // tables are rebuilt, which foreign keys would prevent, and they can't be turned off
+            // within a transaction
+            conn.execute("PRAGMA foreign_keys = OFF").await?;
+            let migrated = Self::migrate(&mut conn).await;
+            conn.execute("PRAGMA foreign_keys = ON").await?;
+            migrated?;
+        }
+        drop(conn);
+
         Ok(Database { inner: db })
     }
+
+    async fn migrate(conn: &mut sqlx::SqliteConnection) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&mut *conn)
+            .await?;
+        let Some(migrations) = usize::try_from(version)
+            .ok()
+            .and_then(|v| Self::MIGRATIONS.get(v..))
+        else {
+            return Err(eyre!(
+                "the database is at version {version}, newer than this build"
+            ));
+        };
+        if migrations.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+        for (i, migration) in migrations.iter().enumerate() {
+            tracing::info!(
+                "Migrating the database to version {}",
+                version as usize + i + 1
+            );
+            let mut s = tx.execute_many(*migration);
+            while s.next().await.transpose()?.is_some() {}
+        }
+        let violations = sqlx::query("PRAGMA foreign_key_check")
+            .fetch_all(&mut *tx)
+            .await?;
+        if !violations.is_empty() {
+            return Err(eyre!(
+                "migrating the database broke {} foreign keys",
+                violations.len()
+            ));
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", Self::MIGRATIONS.len()))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+// used to turn a `UNIQUE` constraint failure into an `Ok(None)`/`Ok(false)`
+pub(crate) fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
 }
 
 macro_rules! defineID {
@@ -0,0 +1,167 @@
+use super::Database;
+use super::clients::ClientId;
+use super::keys::KeyId;
+use super::orgs::OrgId;
+use super::shares::ShareId;
+use super::users::UserId;
+use super::{time_from_db, time_to_db};
+
+use color_eyre::{Result, eyre::eyre};
+use std::str::FromStr;
+
+super::defineID!(AuditId => "audit_log");
+
+/// Who did an audited action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditActor {
+    User(UserId),
+    Client(ClientId),
+    /// Whoever holds the link, nobody known
+    Share(ShareId),
+}
+
+/// How urgently an audited action needs a look
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSeverity {
+    /// Routine access and changes
+    #[default]
+    Info,
+    /// Likely a compromise, alerted on
+    High,
+}
+
+impl AuditSeverity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditSeverity::Info => "info",
+            AuditSeverity::High => "high",
+        }
+    }
+}
+
+impl FromStr for AuditSeverity {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "info" => Ok(AuditSeverity::Info),
+            "high" => Ok(AuditSeverity::High),
+            _ => Err(eyre!("unknown audit severity: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TableAuditLog {
+    pub id: AuditId,
+    pub org: OrgId,
+    pub key_id: Option<KeyId>,
+    pub actor: AuditActor,
+    pub action: String,
+    pub severity: AuditSeverity,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+// raw row of the `audit_log` table, as returned by `SELECT audit_log.*`
+#[allow(non_snake_case)]
+struct RowAuditLog {
+    id: i64,
+    orgID: i64,
+    keyID: Option<i64>,
+    userID: Option<i64>,
+    clientID: Option<i64>,
+    shareID: Option<i64>,
+    action: String,
+    severity: String,
+    at: String,
+}
+
+impl TryFrom<RowAuditLog> for TableAuditLog {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowAuditLog) -> Result<Self> {
+        let actor = match (r.userID, r.clientID, r.shareID) {
+            (Some(user), None, None) => AuditActor::User(UserId(user)),
+            (None, Some(client), None) => AuditActor::Client(ClientId(client)),
+            (None, None, Some(share)) => AuditActor::Share(ShareId(share)),
+            _ => return Err(eyre!("audit entry {} needs exactly one actor", r.id)),
+        };
+        Ok(TableAuditLog {
+            id: AuditId(r.id),
+            org: OrgId(r.orgID),
+            key_id: r.keyID.map(KeyId),
+            actor,
+            action: r.action,
+            severity: AuditSeverity::from_str(&r.severity)?,
+            at: time_from_db(&r.at)?,
+        })
+    }
+}
+
+impl Database {
+    pub async fn log_audit(
+        &self,
+        org: OrgId,
+        key: Option<KeyId>,
+        actor: AuditActor,
+        action: impl AsRef<str>,
+        severity: AuditSeverity,
+    ) -> Result<AuditId> {
+        let action = action.as_ref();
+        let severity = severity.as_str();
+        let key = key.map(|k| k.0);
+        let (user, client, share) = match actor {
+            AuditActor::User(user) => (Some(user.0), None, None),
+            AuditActor::Client(client) => (None, Some(client.0), None),
+            AuditActor::Share(share) => (None, None, Some(share.0)),
+        };
+        let at = time_to_db(chrono::Utc::now());
+
+        let query = sqlx::query!(
+            "INSERT INTO audit_log ('orgID', 'keyID', 'userID', 'clientID', 'shareID', 'action', 'severity', 'at') VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+            org.0,
+            key,
+            user,
+            client,
+            share,
+            action,
+            severity,
+            at
+        )
+        .fetch_one(&self.inner)
+        .await?;
+
+        Ok(AuditId(query.id))
+    }
+
+    // newest first
+    pub async fn get_audit_log_for_key(
+        &self,
+        key: KeyId,
+        limit: i64,
+    ) -> Result<Vec<TableAuditLog>> {
+        sqlx::query_as!(
+            RowAuditLog,
+            "SELECT * FROM audit_log WHERE keyID = ? ORDER BY id DESC LIMIT ?",
+            key.0,
+            limit
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)?
+        .into_iter()
+        .map(TableAuditLog::try_from)
+        .collect()
+    }
+}
@@ -1,12 +1,13 @@
 use crate::database::keys::KeyId;
+use crate::database::orgs::OrgId;
 
 use super::Database;
 use super::Date;
 
-use chrono::DateTime;
+use super::{time_from_db, time_to_db};
+use chrono::{DateTime, Utc};
 use color_eyre::{Result, eyre::eyre};
 use futures::StreamExt;
-use rand::RngCore;
 use sha2::Digest;
 use sqlx::Executor;
 use std::path::Path;
@@ -16,123 +17,157 @@ use std::time::SystemTime;
 super::defineID!(ClientKeyId => "clients_key");
 
 impl Database {
+    // returns `None` if the client and the key aren't in the same namespace
     pub async fn create_clientkey(
         &self,
         client: super::clients::ClientId,
         key: super::keys::KeyId,
-    ) -> Result<(ClientKeyId, String)> {
-        let sha = {
-            let mut sha = [0u8; 32];
-            let mut rng = rand::rng();
-            rng.fill_bytes(&mut sha);
-            sha
-        };
-
-        let token = {
-            let mut s = String::with_capacity(sha.len() * 2);
-            for &x in sha.as_slice() {
-                use std::fmt::Write;
-                write!(s, "{x:02x}").unwrap();
-            }
-            s
-        };
-
-        let tok = token.as_str();
-        let query = sqlx::query!(
-            "INSERT INTO clients_key ('clientID', 'keyID', 'secret') VALUES (?, ?, ?) RETURNING id, secret",
+    ) -> Result<Option<(ClientKeyId, String)>> {
+        // the secret holds the id of the link, so it is only known once the row exists. The
+        // namespaces are compared by the insert itself, as either one may be moving meanwhile
+        let mut tx = self.inner.begin().await?;
+        let Some(id) = sqlx::query_scalar!(
+            "INSERT INTO clients_key ('clientID', 'keyID', 'secret') SELECT clients.id, keys.id, '' FROM clients INNER JOIN keys ON keys.namespace == clients.namespace WHERE clients.id = ? AND keys.id = ? RETURNING id",
             client.0,
             key.0,
-            tok,
         )
-        .fetch_one(&self.inner)
-        .await?;
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let token = crate::clientsecret::new(id);
+        let tok = token.as_str();
+        sqlx::query!("UPDATE clients_key SET secret = ? WHERE id = ?", tok, id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
 
-        Ok((ClientKeyId(query.id), query.secret))
+        Ok(Some((ClientKeyId(id), token)))
     }
 
+    // along with its wrapped secrets, which couldn't be unwrapped anymore
     pub async fn remove_clientkey(&self, key: ClientKeyId) -> Result<bool> {
-        sqlx::query!("DELETE FROM clients_key WHERE id = ?", key.0)
-            .execute(&self.inner)
-            .await
-            .inspect(|s| assert!(s.rows_affected() <= 1, "mutliple key with the same id"))
-            .map(|s| s.rows_affected() == 1)
-            .map_err(color_eyre::Report::from)
+        let mut tx = self.inner.begin().await?;
+        sqlx::query!("DELETE FROM wraps WHERE clientKeyID = ?", key.0)
+            .execute(&mut *tx)
+            .await?;
+        let removed = sqlx::query!("DELETE FROM clients_key WHERE id = ?", key.0)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        assert!(removed <= 1, "mutliple key with the same id");
+        tx.commit().await?;
+        Ok(removed == 1)
     }
 
     pub async fn fetch_client_key_from_client_and_key(
         &self,
+        org: OrgId,
         client: super::clients::ClientId,
         key: KeyId,
     ) -> Result<Option<TableClientsKey>> {
-        let query = sqlx::query!(
-            "SELECT * FROM clients_key WHERE clientID = ? AND keyID = ? LIMIT 1",
+        sqlx::query_as!(
+            RowClientsKey,
+            "SELECT clients_key.* FROM clients_key INNER JOIN clients ON clients.id == clients_key.clientID WHERE clients_key.clientID = ? AND clients_key.keyID = ? AND clients.orgID = ? LIMIT 1",
             client.0,
-            key.0
+            key.0,
+            org.0
         )
         .fetch_optional(&self.inner)
-        .await?;
+        .await?
+        .map(TableClientsKey::try_from)
+        .transpose()
+    }
 
-        query
-            .map(|mut s| {
-                Ok(TableClientsKey {
-                    id: ClientKeyId(s.id),
-                    client_id: super::clients::ClientId(s.clientID),
-                    key_id: super::keys::KeyId(s.keyID),
-                    secret: s.secret,
-                    last_used: s.lastUsed.map(|t| Date::from_str(t.as_str())).transpose()?,
-                })
-            })
-            .transpose()
+    pub async fn fetch_client_key(
+        &self,
+        org: OrgId,
+        key: ClientKeyId,
+    ) -> Result<Option<TableClientsKey>> {
+        sqlx::query_as!(
+            RowClientsKey,
+            "SELECT clients_key.* FROM clients_key INNER JOIN clients ON clients.id == clients_key.clientID WHERE clients_key.id = ? AND clients.orgID = ? LIMIT 1",
+            key.0,
+            org.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableClientsKey::try_from)
+        .transpose()
     }
 
-    pub async fn fetch_client_key(&self, key: ClientKeyId) -> Result<Option<TableClientsKey>> {
-        let query = sqlx::query!("SELECT * FROM clients_key where id = ? LIMIT 1", key.0)
-            .fetch_optional(&self.inner)
-            .await?;
+    // unscoped, for clients authenticated with a signed request
+    pub async fn fetch_client_key_by_id(
+        &self,
+        key: ClientKeyId,
+    ) -> Result<Option<TableClientsKey>> {
+        sqlx::query_as!(
+            RowClientsKey,
+            "SELECT * FROM clients_key WHERE id = ? LIMIT 1",
+            key.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableClientsKey::try_from)
+        .transpose()
+    }
 
-        query
-            .map(|mut s| {
-                Ok(TableClientsKey {
-                    id: ClientKeyId(s.id),
-                    client_id: super::clients::ClientId(s.clientID),
-                    key_id: super::keys::KeyId(s.keyID),
-                    secret: s.secret,
-                    last_used: s.lastUsed.map(|t| Date::from_str(t.as_str())).transpose()?,
-                })
-            })
-            .transpose()
+    // every link between a client of this org and a key
+    pub async fn get_all_client_keys(&self, org: OrgId) -> Result<Vec<TableClientsKey>> {
+        sqlx::query_as!(
+            RowClientsKey,
+            "SELECT clients_key.* FROM clients_key INNER JOIN clients ON clients.id == clients_key.clientID WHERE clients.orgID = ?",
+            org.0
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(TableClientsKey::try_from)
+        .collect()
     }
 
     pub async fn get_client_key_from_secret(
         &self,
         secret: impl AsRef<str>,
     ) -> Result<Option<TableClientsKey>> {
-        let s = secret.as_ref();
-        let query = sqlx::query!("SELECT * FROM clients_key where secret = ? LIMIT 1", s)
-            .fetch_optional(&self.inner)
-            .await?;
+        use crate::clientsecret::ParsedSecret;
 
-        query
-            .map(|mut s| {
-                Ok(TableClientsKey {
-                    id: ClientKeyId(s.id),
-                    client_id: super::clients::ClientId(s.clientID),
-                    key_id: super::keys::KeyId(s.keyID),
-                    secret: s.secret,
-                    last_used: s.lastUsed.map(|t| Date::from_str(t.as_str())).transpose()?,
-                })
-            })
-            .transpose()
+        let s = secret.as_ref();
+        let row = match crate::clientsecret::parse(s) {
+            None => return Ok(None),
+            Some(ParsedSecret::Legacy) => {
+                sqlx::query_as!(
+                    RowClientsKey,
+                    "SELECT * FROM clients_key where secret = ? LIMIT 1",
+                    s
+                )
+                .fetch_optional(&self.inner)
+                .await?
+            }
+            Some(ParsedSecret::Link(id)) => {
+                sqlx::query_as!(
+                    RowClientsKey,
+                    "SELECT * FROM clients_key where id = ? AND secret = ? LIMIT 1",
+                    id,
+                    s
+                )
+                .fetch_optional(&self.inner)
+                .await?
+            }
+        };
+        row.map(TableClientsKey::try_from).transpose()
     }
 
-    // return true if the client_key has been updated
-    pub async fn update_client_key_last_used(&self, key: ClientKeyId) -> Result<bool> {
+    // count a use of the link and set its last use, return false if it has no use left
+    pub async fn record_client_key_use(&self, key: ClientKeyId) -> Result<bool> {
         let date = DateTime::<chrono::Utc>::from(SystemTime::now());
         let date = Date(date.date_naive());
         let date = date.to_string();
 
         sqlx::query!(
-            "UPDATE clients_key SET lastUsed = ? WHERE id = ?",
+            "UPDATE clients_key SET lastUsed = ?, uses = uses + 1 WHERE id = ? AND (maxUses IS NULL OR uses < maxUses)",
             date,
             key.0
         )
@@ -148,23 +183,50 @@ impl Database {
         .map_err(color_eyre::Report::from)
     }
 
+    pub async fn update_client_key_limits(
+        &self,
+        key: ClientKeyId,
+        limits: &LinkLimits,
+    ) -> Result<()> {
+        let valid_from = limits.valid_from.map(time_to_db);
+        let expires_at = limits.expires_at.map(time_to_db);
+        sqlx::query!(
+            "UPDATE clients_key SET validFrom = ?, expiresAt = ?, maxUses = ? WHERE id = ?",
+            valid_from,
+            expires_at,
+            limits.max_uses,
+            key.0
+        )
+        .execute(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)
+        .map(|_| ())
+    }
+
+    // delete the links expired at `now`, or without any use left, return how many
+    pub async fn remove_expired_client_keys(&self, now: DateTime<Utc>) -> Result<u64> {
+        let now = time_to_db(now);
+        let mut tx = self.inner.begin().await?;
+        sqlx::query!(
+            "DELETE FROM wraps WHERE clientKeyID IN (SELECT id FROM clients_key WHERE expiresAt <= ? OR (maxUses IS NOT NULL AND uses >= maxUses))",
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        let removed = sqlx::query!(
+            "DELETE FROM clients_key WHERE expiresAt <= ? OR (maxUses IS NOT NULL AND uses >= maxUses)",
+            now
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(removed)
+    }
+
     // update the secret used by the client_key
     pub async fn update_client_secret(&self, key: ClientKeyId) -> Result<Option<String>> {
-        let sha = {
-            let mut sha = [0u8; 32];
-            let mut rng = rand::rng();
-            rng.fill_bytes(&mut sha);
-            sha
-        };
-
-        let token = {
-            let mut s = String::with_capacity(sha.len() * 2);
-            for &x in sha.as_slice() {
-                use std::fmt::Write;
-                write!(s, "{x:02x}").unwrap();
-            }
-            s
-        };
+        let token = crate::clientsecret::new(key.0);
         let s = token.as_str();
 
         sqlx::query!("UPDATE clients_key SET secret = ? WHERE id = ?", s, key.0)
@@ -181,20 +243,47 @@ impl Database {
             .map(|c| c.then_some(token))
     }
 
+    pub async fn update_client_key_allowed_cidrs(
+        &self,
+        key: ClientKeyId,
+        ranges: &[ipnet::IpNet],
+    ) -> Result<()> {
+        let ranges = crate::allowlist::to_db(ranges);
+        sqlx::query!(
+            "UPDATE clients_key SET allowedCidrs = ? WHERE id = ?",
+            ranges,
+            key.0
+        )
+        .execute(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)
+        .map(|_| ())
+    }
+
     pub async fn delete_all_with_key_id(&self, key: super::keys::KeyId) -> Result<u64> {
-        sqlx::query!("DELETE FROM clients_key WHERE keyID = ?", key.0)
-            .execute(&self.inner)
-            .await
-            .map(|s| s.rows_affected())
-            .map_err(color_eyre::Report::from)
+        let mut tx = self.inner.begin().await?;
+        sqlx::query!("DELETE FROM wraps WHERE keyID = ?", key.0)
+            .execute(&mut *tx)
+            .await?;
+        let removed = sqlx::query!("DELETE FROM clients_key WHERE keyID = ?", key.0)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok(removed)
     }
 
     pub async fn delete_all_with_client_id(&self, client: super::clients::ClientId) -> Result<u64> {
-        sqlx::query!("DELETE FROM clients_key WHERE clientID = ?", client.0)
-            .execute(&self.inner)
-            .await
-            .map(|s| s.rows_affected())
-            .map_err(color_eyre::Report::from)
+        let mut tx = self.inner.begin().await?;
+        sqlx::query!("DELETE FROM wraps WHERE clientID = ?", client.0)
+            .execute(&mut *tx)
+            .await?;
+        let removed = sqlx::query!("DELETE FROM clients_key WHERE clientID = ?", client.0)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok(removed)
     }
 }
 
@@ -205,4 +294,77 @@ pub struct TableClientsKey {
     pub key_id: super::keys::KeyId,
     pub secret: String,
     pub last_used: Option<Date>,
+    /// IP ranges this link may be used from, on top of the client's own
+    pub allowed_cidrs: Vec<ipnet::IpNet>,
+    pub limits: LinkLimits,
+    pub uses: i64,
+}
+
+/// When, and how many times, a link can be used, without bound when missing
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+pub struct LinkLimits {
+    #[serde(default)]
+    pub valid_from: Option<DateTime<Utc>>,
+    /// The link is deleted once expired
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub max_uses: Option<i64>,
+}
+
+impl LinkLimits {
+    pub fn is_valid(&self) -> bool {
+        let ordered = match (self.valid_from, self.expires_at) {
+            (Some(from), Some(to)) => from < to,
+            _ => true,
+        };
+        ordered && self.max_uses.is_none_or(|m| m > 0)
+    }
+}
+
+impl TableClientsKey {
+    /// Whether the link can be used at `now`: within its validity, with uses left
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.limits.valid_from.is_none_or(|from| from <= now)
+            && self.limits.expires_at.is_none_or(|to| now < to)
+            && self.limits.max_uses.is_none_or(|max| self.uses < max)
+    }
+}
+
+// raw row of the `clients_key` table, as returned by `SELECT clients_key.*`
+#[allow(non_snake_case)]
+struct RowClientsKey {
+    id: i64,
+    clientID: i64,
+    keyID: i64,
+    secret: String,
+    lastUsed: Option<String>,
+    allowedCidrs: String,
+    validFrom: Option<String>,
+    expiresAt: Option<String>,
+    maxUses: Option<i64>,
+    uses: i64,
+}
+
+impl TryFrom<RowClientsKey> for TableClientsKey {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowClientsKey) -> Result<Self> {
+        Ok(TableClientsKey {
+            id: ClientKeyId(r.id),
+            client_id: super::clients::ClientId(r.clientID),
+            key_id: super::keys::KeyId(r.keyID),
+            secret: r.secret,
+            last_used: r.lastUsed.map(|t| Date::from_str(t.as_str())).transpose()?,
+            allowed_cidrs: crate::allowlist::from_db(&r.allowedCidrs)?,
+            limits: LinkLimits {
+                valid_from: r.validFrom.as_deref().map(time_from_db).transpose()?,
+                expires_at: r.expiresAt.as_deref().map(time_from_db).transpose()?,
+                max_uses: r.maxUses,
+            },
+            uses: r.uses,
+        })
+    }
 }
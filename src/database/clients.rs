@@ -1,4 +1,7 @@
 use super::Database;
+use super::Metadata;
+use super::keys::KeyId;
+use super::orgs::OrgId;
 
 use color_eyre::{Result, eyre::eyre};
 use futures::StreamExt;
@@ -8,24 +11,141 @@ use std::path::Path;
 
 super::defineID!(ClientId => "clients");
 
+/// How a client is recognised from its TLS certificate, either one is enough
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+pub struct ClientCertificate {
+    /// Lowercase hex SHA-256 of the certificate (DER)
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    /// A subject alternative name (DNS name, URI, email, IP) of the certificates
+    #[serde(default)]
+    pub san: Option<String>,
+}
+
+impl ClientCertificate {
+    /// The fingerprint in lowercase hex without `:`, blank values as missing.
+    /// `None` if the fingerprint isn't a SHA-256
+    pub fn normalize(self) -> Option<Self> {
+        let fingerprint = self
+            .fingerprint
+            .map(|f| f.trim().replace(':', "").to_ascii_lowercase())
+            .filter(|f| !f.is_empty());
+        if fingerprint
+            .as_ref()
+            .is_some_and(|f| f.len() != 64 || !f.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            return None;
+        }
+        let san = self
+            .san
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        Some(Self { fingerprint, san })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TableClients {
     pub id: ClientId,
+    pub org: OrgId,
+    pub namespace: String,
     pub name: String,
     pub description: String,
+    pub metadata: Metadata,
+    /// IP ranges the client may use its secrets from, anywhere when empty
+    pub allowed_cidrs: Vec<ipnet::IpNet>,
+    pub certificate: ClientCertificate,
+    /// Access tokens issued under another generation are revoked
+    pub token_generation: i64,
+}
+
+// raw row of the `clients` table, as returned by `SELECT clients.*`
+#[allow(non_snake_case)]
+struct RowClients {
+    id: i64,
+    orgID: i64,
+    namespace: String,
+    name: String,
+    description: String,
+    tags: String,
+    owner: Option<String>,
+    contact: Option<String>,
+    provider: Option<String>,
+    runbook: Option<String>,
+    environment: Option<String>,
+    allowedCidrs: String,
+    tlsFingerprint: Option<String>,
+    tlsSan: Option<String>,
+    tokenGeneration: i64,
+}
+
+impl TryFrom<RowClients> for TableClients {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowClients) -> Result<Self> {
+        Ok(TableClients {
+            id: ClientId(r.id),
+            org: OrgId(r.orgID),
+            namespace: r.namespace,
+            name: r.name,
+            description: r.description,
+            metadata: Metadata {
+                tags: Metadata::tags_from_db(&r.tags)?,
+                owner: r.owner,
+                contact: r.contact,
+                provider: r.provider,
+                runbook: r.runbook,
+                environment: r.environment,
+            },
+            allowed_cidrs: crate::allowlist::from_db(&r.allowedCidrs)?,
+            certificate: ClientCertificate {
+                fingerprint: r.tlsFingerprint,
+                san: r.tlsSan,
+            },
+            token_generation: r.tokenGeneration,
+        })
+    }
+}
+
+/// What `update_client` changes, the namespace, metadata, allowed ranges and certificate are
+/// kept when `None`
+pub struct ClientUpdate<'a> {
+    pub namespace: Option<&'a str>,
+    pub name: &'a str,
+    pub desc: &'a str,
+    pub metadata: Option<&'a Metadata>,
+    pub allowed_cidrs: Option<&'a [ipnet::IpNet]>,
+    /// Already normalized
+    pub certificate: Option<&'a ClientCertificate>,
+}
+
+/// Why `update_client` left a client untouched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientUpdateConflict {
+    /// It is linked to keys outside of its new namespace
+    LinkedOutsideNamespace,
+    /// Another client of the org has the certificate
+    CertificateTaken,
 }
 
 impl Database {
     pub async fn create_client(
         &self,
+        org: OrgId,
+        namespace: impl AsRef<str>,
         name: impl AsRef<str>,
         desc: impl AsRef<str>,
     ) -> Result<ClientId> {
+        let namespace = namespace.as_ref();
         let name = name.as_ref();
         let desc = desc.as_ref();
 
         let query = sqlx::query!(
-            "INSERT INTO clients ('name', 'description') VALUES (?, ?) RETURNING id",
+            "INSERT INTO clients ('orgID', 'namespace', 'name', 'description') VALUES (?, ?, ?, ?) RETURNING id",
+            org.0,
+            namespace,
             name,
             desc,
         )
@@ -34,16 +154,116 @@ impl Database {
         Ok(ClientId(query.id))
     }
 
-    pub async fn fetch_client(&self, client: ClientId) -> Result<Option<TableClients>> {
-        let query = sqlx::query!("SELECT * FROM clients where id = ? LIMIT 1", client.0)
+    pub async fn fetch_client(&self, org: OrgId, client: ClientId) -> Result<Option<TableClients>> {
+        sqlx::query_as!(
+            RowClients,
+            "SELECT * FROM clients where id = ? AND orgID = ? LIMIT 1",
+            client.0,
+            org.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableClients::try_from)
+        .transpose()
+    }
+
+    // unscoped, for clients authenticated with one of their secrets
+    pub async fn fetch_client_by_id(&self, client: ClientId) -> Result<Option<TableClients>> {
+        sqlx::query_as!(
+            RowClients,
+            "SELECT * FROM clients where id = ? LIMIT 1",
+            client.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableClients::try_from)
+        .transpose()
+    }
+
+    // among the clients linked to `key`, the one whose registered fingerprint, or else one of
+    // the SANs, matches: certificates are only unique within an org
+    pub async fn fetch_client_by_certificate(
+        &self,
+        key: KeyId,
+        fingerprint: impl AsRef<str>,
+        sans: &[String],
+    ) -> Result<Option<TableClients>> {
+        let fingerprint = fingerprint.as_ref();
+        let client = sqlx::query_as!(
+            RowClients,
+            "SELECT clients.* FROM clients INNER JOIN clients_key ON clients_key.clientID = clients.id WHERE clients_key.keyID = ? AND clients.tlsFingerprint = ? LIMIT 1",
+            key.0,
+            fingerprint
+        )
+        .fetch_optional(&self.inner)
+        .await?;
+        if let Some(client) = client {
+            return client.try_into().map(Some);
+        }
+        for san in sans {
+            if let Some(client) = sqlx::query_as!(
+                RowClients,
+                "SELECT clients.* FROM clients INNER JOIN clients_key ON clients_key.clientID = clients.id WHERE clients_key.keyID = ? AND clients.tlsSan = ? LIMIT 1",
+                key.0,
+                san
+            )
             .fetch_optional(&self.inner)
-            .await?;
+            .await?
+            {
+                return client.try_into().map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    // return false if the fingerprint or the SAN is already registered on another client of the org
+    pub async fn update_client_certificate(
+        &self,
+        client: ClientId,
+        certificate: &ClientCertificate,
+    ) -> Result<bool> {
+        match sqlx::query!(
+            "UPDATE clients SET tlsFingerprint = ?, tlsSan = ? WHERE id = ?",
+            certificate.fingerprint,
+            certificate.san,
+            client.0
+        )
+        .execute(&self.inner)
+        .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if super::is_unique_violation(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // revoke every access token issued to the client, return false if it doesn't exist
+    pub async fn rotate_client_tokens(&self, client: ClientId) -> Result<bool> {
+        sqlx::query!(
+            "UPDATE clients SET tokenGeneration = tokenGeneration + 1 WHERE id = ?",
+            client.0
+        )
+        .execute(&self.inner)
+        .await
+        .map(|s| s.rows_affected() == 1)
+        .map_err(color_eyre::Report::from)
+    }
 
-        Ok(query.map(|mut s| TableClients {
-            id: ClientId(s.id),
-            name: s.name,
-            description: s.description,
-        }))
+    pub async fn update_client_allowed_cidrs(
+        &self,
+        client: ClientId,
+        ranges: &[ipnet::IpNet],
+    ) -> Result<()> {
+        let ranges = crate::allowlist::to_db(ranges);
+        sqlx::query!(
+            "UPDATE clients SET allowedCidrs = ? WHERE id = ?",
+            ranges,
+            client.0
+        )
+        .execute(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)
+        .map(|_| ())
     }
 
     pub async fn remove_client(&self, client: ClientId) -> Result<bool> {
@@ -55,35 +275,110 @@ impl Database {
             .map_err(color_eyre::Report::from)
     }
 
-    pub async fn get_all_clients(&self) -> Result<Vec<TableClients>> {
-        sqlx::query!("SELECT * FROM clients")
+    pub async fn get_all_clients(&self, org: OrgId) -> Result<Vec<TableClients>> {
+        sqlx::query_as!(RowClients, "SELECT * FROM clients WHERE orgID = ?", org.0)
             .fetch_all(&self.inner)
             .await
-            .map_err(color_eyre::Report::from)
-            .map(|v| {
-                v.into_iter()
-                    .map(|r| TableClients {
-                        id: ClientId(r.id),
-                        name: r.name,
-                        description: r.description,
-                    })
-                    .collect()
-            })
+            .map_err(color_eyre::Report::from)?
+            .into_iter()
+            .map(TableClients::try_from)
+            .collect()
     }
 
-    pub async fn update_client_info(
+    // everything is written at once, or nothing when it conflicts
+    pub async fn update_client(
         &self,
         client: ClientId,
-        name: impl AsRef<str>,
-        desc: impl AsRef<str>,
-    ) -> Result<()> {
-        let name = name.as_ref();
-        let desc = desc.as_ref();
+        update: &ClientUpdate<'_>,
+    ) -> Result<Result<(), ClientUpdateConflict>> {
+        if update.metadata.is_some_and(|m| !m.is_valid_runbook()) {
+            return Err(eyre!("runbook isn't an http(s) URL"));
+        }
+        // writing first holds the write lock, so no link can be created before the check below
+        let mut tx = self.inner.begin().await?;
+        sqlx::query!(
+            "UPDATE clients SET namespace = COALESCE(?, namespace), name = ?, description = ? WHERE id = ?",
+            update.namespace,
+            update.name,
+            update.desc,
+            client.0
+        )
+        .execute(&mut *tx)
+        .await?;
+        if update.namespace.is_some()
+            && sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM clients_key INNER JOIN clients ON clients.id == clients_key.clientID INNER JOIN keys ON keys.id == clients_key.keyID WHERE clients_key.clientID = ? AND clients.namespace != keys.namespace",
+                client.0
+            )
+            .fetch_one(&mut *tx)
+            .await?
+                != 0
+        {
+            return Ok(Err(ClientUpdateConflict::LinkedOutsideNamespace));
+        }
+        if let Some(metadata) = update.metadata {
+            let tags = metadata.tags_to_db();
+            sqlx::query!(
+                "UPDATE clients SET tags = ?, owner = ?, contact = ?, provider = ?, runbook = ?, environment = ? WHERE id = ?",
+                tags,
+                metadata.owner,
+                metadata.contact,
+                metadata.provider,
+                metadata.runbook,
+                metadata.environment,
+                client.0
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some(ranges) = update.allowed_cidrs {
+            let ranges = crate::allowlist::to_db(ranges);
+            sqlx::query!(
+                "UPDATE clients SET allowedCidrs = ? WHERE id = ?",
+                ranges,
+                client.0
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some(certificate) = update.certificate {
+            match sqlx::query!(
+                "UPDATE clients SET tlsFingerprint = ?, tlsSan = ? WHERE id = ?",
+                certificate.fingerprint,
+                certificate.san,
+                client.0
+            )
+            .execute(&mut *tx)
+            .await
+            {
+                Ok(_) => (),
+                Err(e) if super::is_unique_violation(&e) => {
+                    return Ok(Err(ClientUpdateConflict::CertificateTaken));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        tx.commit().await?;
+        Ok(Ok(()))
+    }
 
+    pub async fn update_client_metadata(
+        &self,
+        client: ClientId,
+        metadata: &Metadata,
+    ) -> Result<()> {
+        if !metadata.is_valid_runbook() {
+            return Err(eyre!("runbook isn't an http(s) URL"));
+        }
+        let tags = metadata.tags_to_db();
         sqlx::query!(
-            "UPDATE clients SET name = ?, description = ? WHERE id = ?",
-            name,
-            desc,
+            "UPDATE clients SET tags = ?, owner = ?, contact = ?, provider = ?, runbook = ?, environment = ? WHERE id = ?",
+            tags,
+            metadata.owner,
+            metadata.contact,
+            metadata.provider,
+            metadata.runbook,
+            metadata.environment,
             client.0
         )
         .execute(&self.inner)
@@ -1,18 +1,52 @@
--- init.sql
+-- init.sql, the latest schema: changing an existing table also needs a migration in `migrations/`,
+-- and as queries read columns by position, one that rebuilds the table unless new columns go last
 CREATE TABLE IF NOT EXISTS clients (
   id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL, -- the organisation owning this client
+  namespace TEXT NOT NULL DEFAULT 'default', -- a client can only be linked to keys of the same namespace
   name TEXT NOT NULL,
-  description TEXT NOT NULL
+  description TEXT NOT NULL,
+  -- metadata
+  tags TEXT NOT NULL DEFAULT '[]', -- json array of free-form tags
+  owner TEXT, -- owning team
+  contact TEXT, -- who to contact about this
+  provider TEXT, -- upstream provider
+  runbook TEXT, -- runbook URL
+  environment TEXT,
+  allowedCidrs TEXT NOT NULL DEFAULT '[]', -- json array of the IP ranges the client may connect from, anywhere when empty
+  tlsFingerprint TEXT, -- SHA-256 (hex) of a client certificate the client may authenticate with
+  tlsSan TEXT, -- or a subject alternative name its certificates carry
+  tokenGeneration INTEGER NOT NULL DEFAULT 0, -- bumped to revoke every access token issued to the client
+  --
+  UNIQUE (orgID, tlsFingerprint),
+  UNIQUE (orgID, tlsSan),
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
 );
 
 CREATE TABLE IF NOT EXISTS keys (
   id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL, -- the organisation owning this key
+  namespace TEXT NOT NULL DEFAULT 'default', -- prod, staging, dev, ...
   name TEXT NOT NULL,
   description TEXT NOT NULL,
+  kind TEXT NOT NULL DEFAULT 'text', -- what the key holds, see `KeyKind`
   -- nonce BLOB NOT NULL, -- used to encrypt/decrypt the above data
   apiKey TEXT, -- they actual api key
   rotateAt TEXT, -- try to autorotate the key at <DATE> where <DATE> is a `YYYY-MM-DD`
-  rotateWith TEXT -- what to autorotate with
+  rotateWith TEXT, -- what to autorotate with
+  leaseTtl INTEGER, -- seconds, when set every retrieval grants a lease to renew within it
+  honeytoken BOOLEAN NOT NULL DEFAULT FALSE, -- a canary, any access to it raises an alert
+  -- metadata
+  tags TEXT NOT NULL DEFAULT '[]', -- json array of free-form tags
+  owner TEXT, -- owning team
+  contact TEXT, -- who to contact about this
+  provider TEXT, -- upstream provider
+  runbook TEXT, -- runbook URL
+  environment TEXT,
+  jwksId TEXT UNIQUE, -- random, addresses the public JWKS of a signing key
+  --
+  UNIQUE (orgID, namespace, name),
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
 );
 
 CREATE TABLE IF NOT EXISTS clients_key (
@@ -21,14 +55,154 @@ CREATE TABLE IF NOT EXISTS clients_key (
   keyID INTEGER NOT NULL, -- the key id
   secret TEXT NOT NULL, -- the secret that the client will need to provide
   lastUsed TEXT, -- unix timestamp
+  allowedCidrs TEXT NOT NULL DEFAULT '[]', -- json array of IP ranges, on top of the client's own
+  validFrom TEXT, -- RFC 3339, the link can't be used before
+  expiresAt TEXT, -- RFC 3339, the link can't be used from then on, and gets deleted
+  maxUses INTEGER, -- how many times the link can be used, unlimited when NULL
+  uses INTEGER NOT NULL DEFAULT 0,
   --
   UNIQUE (clientID, keyID),
   FOREIGN KEY (clientID) REFERENCES clients (id),
   FOREIGN KEY (keyID) REFERENCES keys (id)
 );
 
+CREATE TABLE IF NOT EXISTS keys_fields (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  keyID INTEGER NOT NULL, -- the key (of kind `fields`) holding this field
+  name TEXT NOT NULL, -- also used as the env variable name
+  kind TEXT NOT NULL DEFAULT 'text', -- see `FieldKind`
+  value TEXT NOT NULL,
+  --
+  UNIQUE (keyID, name),
+  FOREIGN KEY (keyID) REFERENCES keys (id)
+);
+
+CREATE TABLE IF NOT EXISTS keys_files (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  keyID INTEGER NOT NULL UNIQUE, -- the key (of kind `file`) holding this file
+  filename TEXT NOT NULL,
+  contentType TEXT NOT NULL DEFAULT 'application/octet-stream',
+  content BLOB NOT NULL,
+  --
+  FOREIGN KEY (keyID) REFERENCES keys (id)
+);
+
+CREATE TABLE IF NOT EXISTS keys_certs (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  keyID INTEGER NOT NULL UNIQUE, -- the key whose secret (or file) is this certificate
+  subject TEXT NOT NULL,
+  issuer TEXT NOT NULL,
+  sans TEXT NOT NULL DEFAULT '[]', -- JSON array of strings
+  notAfter TEXT NOT NULL, -- date (YYYY-MM-DD)
+  --
+  FOREIGN KEY (keyID) REFERENCES keys (id)
+);
+
+CREATE TABLE IF NOT EXISTS keys_versions (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  keyID INTEGER NOT NULL, -- the managed key (of kind `signing`, `transit` or `ssh`) this is a version of
+  version INTEGER NOT NULL, -- starts at 1, the highest one is the current one
+  algorithm TEXT NOT NULL,
+  material BLOB NOT NULL, -- the private key (PKCS#8 DER or OpenSSH) or the data-encryption key
+  createdAt TEXT NOT NULL, -- `YYYY-MM-DD`
+  retireAt TEXT, -- `YYYY-MM-DD` at which a rotated version stops being published
+  --
+  UNIQUE (keyID, version),
+  FOREIGN KEY (keyID) REFERENCES keys (id)
+);
+
+CREATE TABLE IF NOT EXISTS keys_totp (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  keyID INTEGER NOT NULL UNIQUE, -- the key (of kind `totp`) holding this seed
+  secret TEXT NOT NULL, -- base32, never returned
+  algorithm TEXT NOT NULL DEFAULT 'SHA1',
+  digits INTEGER NOT NULL DEFAULT 6,
+  period INTEGER NOT NULL DEFAULT 30, -- seconds
+  issuer TEXT,
+  account TEXT,
+  --
+  FOREIGN KEY (keyID) REFERENCES keys (id)
+);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  keyID INTEGER, -- the key the action was about, if any
+  userID INTEGER, -- who did it, either a user
+  clientID INTEGER, -- or a client
+  shareID INTEGER, -- or whoever opened a share link
+  action TEXT NOT NULL,
+  severity TEXT NOT NULL DEFAULT 'info', -- see `AuditSeverity`
+  at TEXT NOT NULL, -- RFC 3339
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+
 CREATE TABLE IF NOT EXISTS users (
   id INTEGER PRIMARY KEY ASC AUTOINCREMENT,
   name TEXT NOT NULL,
   token TEXT NOT NULL
 );
+
+CREATE TABLE IF NOT EXISTS orgs (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS orgs_members (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  userID INTEGER NOT NULL,
+  --
+  UNIQUE (orgID, userID),
+  FOREIGN KEY (orgID) REFERENCES orgs (id),
+  FOREIGN KEY (userID) REFERENCES users (id)
+);
+
+CREATE TABLE IF NOT EXISTS leases (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  keyID INTEGER NOT NULL, -- the leased key
+  clientID INTEGER NOT NULL, -- who holds it, kept once the client is gone
+  clientKeyID INTEGER NOT NULL, -- the link it was granted through, kept once the link is gone
+  lease TEXT NOT NULL UNIQUE, -- handed to the client to renew the lease with
+  ttl INTEGER NOT NULL, -- seconds, each renewal pushes the expiry back by it
+  grantedAt TEXT NOT NULL, -- RFC 3339
+  expiresAt TEXT NOT NULL, -- RFC 3339, pushed back on each renewal
+  revokedAt TEXT, -- RFC 3339
+  --
+  FOREIGN KEY (keyID) REFERENCES keys (id)
+);
+
+CREATE TABLE IF NOT EXISTS shares (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  keyID INTEGER NOT NULL, -- the shared key
+  userID INTEGER NOT NULL, -- who created the link
+  token TEXT NOT NULL UNIQUE, -- in the fragment of the link
+  passphrase TEXT, -- PBKDF2 of the passphrase, if any
+  failedAttempts INTEGER NOT NULL DEFAULT 0, -- wrong passphrases so far
+  createdAt TEXT NOT NULL, -- RFC 3339
+  expiresAt TEXT NOT NULL, -- RFC 3339
+  burnedAt TEXT, -- RFC 3339, once opened, revoked, or out of attempts
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id),
+  FOREIGN KEY (keyID) REFERENCES keys (id),
+  FOREIGN KEY (userID) REFERENCES users (id)
+);
+
+CREATE TABLE IF NOT EXISTS wraps (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  keyID INTEGER NOT NULL,
+  clientID INTEGER NOT NULL,
+  clientKeyID INTEGER NOT NULL, -- the link whose secret is wrapped, deleted along with the link
+  userID INTEGER NOT NULL, -- who wrapped it
+  token TEXT NOT NULL UNIQUE, -- unwrapped once for the secret
+  createdAt TEXT NOT NULL, -- RFC 3339
+  expiresAt TEXT NOT NULL, -- RFC 3339
+  unwrappedAt TEXT, -- RFC 3339
+  unwrappedFrom TEXT, -- address that unwrapped it, to tell it apart from a later replay
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id),
+  FOREIGN KEY (userID) REFERENCES users (id)
+);
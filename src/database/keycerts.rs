@@ -0,0 +1,112 @@
+use super::Database;
+use super::Date;
+use super::keys::KeyId;
+use super::orgs::OrgId;
+use crate::certs::CertInfo;
+
+use color_eyre::{Result, eyre::eyre};
+use std::str::FromStr;
+
+super::defineID!(KeyCertId => "keys_certs");
+
+#[derive(Debug, Clone)]
+pub struct TableKeysCerts {
+    pub id: KeyCertId,
+    pub key_id: KeyId,
+    pub cert: CertInfo,
+}
+
+// raw row of the `keys_certs` table, as returned by `SELECT keys_certs.*`
+#[allow(non_snake_case)]
+struct RowKeysCerts {
+    id: i64,
+    keyID: i64,
+    subject: String,
+    issuer: String,
+    sans: String,
+    notAfter: String,
+}
+
+impl TryFrom<RowKeysCerts> for TableKeysCerts {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowKeysCerts) -> Result<Self> {
+        Ok(TableKeysCerts {
+            id: KeyCertId(r.id),
+            key_id: KeyId(r.keyID),
+            cert: CertInfo {
+                subject: r.subject,
+                issuer: r.issuer,
+                sans: serde_json::from_str(&r.sans)?,
+                not_after: Date::from_str(&r.notAfter)?,
+            },
+        })
+    }
+}
+
+impl Database {
+    // create the certificate of the key, or replace it if there is already one
+    pub async fn set_key_cert(&self, key: KeyId, cert: &CertInfo) -> Result<KeyCertId> {
+        let sans = serde_json::to_string(&cert.sans)?;
+        let not_after = cert.not_after.to_string();
+
+        let query = sqlx::query!(
+            "INSERT INTO keys_certs ('keyID', 'subject', 'issuer', 'sans', 'notAfter') VALUES (?, ?, ?, ?, ?) ON CONFLICT (keyID) DO UPDATE SET subject = excluded.subject, issuer = excluded.issuer, sans = excluded.sans, notAfter = excluded.notAfter RETURNING id",
+            key.0,
+            cert.subject,
+            cert.issuer,
+            sans,
+            not_after
+        )
+        .fetch_one(&self.inner)
+        .await?;
+
+        Ok(KeyCertId(query.id))
+    }
+
+    pub async fn fetch_key_cert(&self, key: KeyId) -> Result<Option<TableKeysCerts>> {
+        sqlx::query_as!(
+            RowKeysCerts,
+            "SELECT * FROM keys_certs WHERE keyID = ? LIMIT 1",
+            key.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeysCerts::try_from)
+        .transpose()
+    }
+
+    // every certificate of the org expiring at or before `before`, soonest first
+    pub async fn get_expiring_certs(
+        &self,
+        org: OrgId,
+        before: Date,
+    ) -> Result<Vec<(super::keys::TableKeys, TableKeysCerts)>> {
+        let before = before.to_string();
+        let certs = sqlx::query_as!(
+            RowKeysCerts,
+            "SELECT keys_certs.* FROM keys_certs INNER JOIN keys ON keys.id == keys_certs.keyID WHERE keys.orgID = ? AND keys_certs.notAfter <= ? ORDER BY keys_certs.notAfter",
+            org.0,
+            before
+        )
+        .fetch_all(&self.inner)
+        .await?;
+
+        let mut out = Vec::with_capacity(certs.len());
+        for cert in certs {
+            let cert = TableKeysCerts::try_from(cert)?;
+            if let Some(key) = self.fetch_key(org, cert.key_id).await? {
+                out.push((key, cert));
+            }
+        }
+        Ok(out)
+    }
+
+    pub async fn delete_cert_with_key_id(&self, key: KeyId) -> Result<bool> {
+        sqlx::query!("DELETE FROM keys_certs WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected() == 1)
+            .map_err(color_eyre::Report::from)
+    }
+}
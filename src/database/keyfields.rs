@@ -0,0 +1,162 @@
+use super::Database;
+use super::keys::KeyId;
+
+use color_eyre::{Result, eyre::eyre};
+use std::str::FromStr;
+
+super::defineID!(KeyFieldId => "keys_fields");
+
+/// How the value of a field is checked and displayed
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    /// Not sensitive (an username, a client ID, ...)
+    #[default]
+    Text,
+    /// Sensitive (a password, a secret key, ...)
+    Secret,
+    /// Must be a valid URL (a DSN, an endpoint, ...)
+    Url,
+    /// Must be an integer (a port, ...)
+    Integer,
+}
+
+impl FieldKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FieldKind::Text => "text",
+            FieldKind::Secret => "secret",
+            FieldKind::Url => "url",
+            FieldKind::Integer => "integer",
+        }
+    }
+
+    pub fn is_valid(self, value: &str) -> bool {
+        match self {
+            FieldKind::Text | FieldKind::Secret => true,
+            FieldKind::Url => url::Url::parse(value).is_ok(),
+            FieldKind::Integer => value.parse::<i64>().is_ok(),
+        }
+    }
+}
+
+impl FromStr for FieldKind {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(FieldKind::Text),
+            "secret" => Ok(FieldKind::Secret),
+            "url" => Ok(FieldKind::Url),
+            "integer" => Ok(FieldKind::Integer),
+            _ => Err(eyre!("unknown field kind: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TableKeysFields {
+    pub id: KeyFieldId,
+    pub key_id: KeyId,
+    pub name: String,
+    pub kind: FieldKind,
+    pub value: String,
+}
+
+// raw row of the `keys_fields` table, as returned by `SELECT keys_fields.*`
+#[allow(non_snake_case)]
+struct RowKeysFields {
+    id: i64,
+    keyID: i64,
+    name: String,
+    kind: String,
+    value: String,
+}
+
+impl TryFrom<RowKeysFields> for TableKeysFields {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowKeysFields) -> Result<Self> {
+        Ok(TableKeysFields {
+            id: KeyFieldId(r.id),
+            key_id: KeyId(r.keyID),
+            name: r.name,
+            kind: FieldKind::from_str(&r.kind)?,
+            value: r.value,
+        })
+    }
+}
+
+impl Database {
+    // create the field, or replace it if one with the same name already exists
+    pub async fn set_key_field(
+        &self,
+        key: KeyId,
+        name: impl AsRef<str>,
+        kind: FieldKind,
+        value: impl AsRef<str>,
+    ) -> Result<KeyFieldId> {
+        let name = name.as_ref();
+        let kind = kind.as_str();
+        let value = value.as_ref();
+
+        let query = sqlx::query!(
+            "INSERT INTO keys_fields ('keyID', 'name', 'kind', 'value') VALUES (?, ?, ?, ?) ON CONFLICT (keyID, name) DO UPDATE SET kind = excluded.kind, value = excluded.value RETURNING id",
+            key.0,
+            name,
+            kind,
+            value
+        )
+        .fetch_one(&self.inner)
+        .await?;
+
+        Ok(KeyFieldId(query.id))
+    }
+
+    pub async fn get_all_key_fields(&self, key: KeyId) -> Result<Vec<TableKeysFields>> {
+        sqlx::query_as!(
+            RowKeysFields,
+            "SELECT * FROM keys_fields WHERE keyID = ? ORDER BY id",
+            key.0
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)?
+        .into_iter()
+        .map(TableKeysFields::try_from)
+        .collect()
+    }
+
+    pub async fn remove_key_field(&self, key: KeyId, name: impl AsRef<str>) -> Result<bool> {
+        let name = name.as_ref();
+        sqlx::query!(
+            "DELETE FROM keys_fields WHERE keyID = ? AND name = ?",
+            key.0,
+            name
+        )
+        .execute(&self.inner)
+        .await
+        .inspect(|s| assert!(s.rows_affected() <= 1, "mutliple fields with the same name"))
+        .map(|s| s.rows_affected() == 1)
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn delete_all_fields_with_key_id(&self, key: KeyId) -> Result<u64> {
+        sqlx::query!("DELETE FROM keys_fields WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected())
+            .map_err(color_eyre::Report::from)
+    }
+}
@@ -0,0 +1,95 @@
+use super::Database;
+use super::keys::KeyId;
+
+use color_eyre::{Result, eyre::eyre};
+
+super::defineID!(KeyFileId => "keys_files");
+
+/// Everything about a stored file but its content
+#[derive(Debug, Clone)]
+pub struct TableKeysFilesInfo {
+    pub id: KeyFileId,
+    pub key_id: KeyId,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableKeysFiles {
+    pub id: KeyFileId,
+    pub key_id: KeyId,
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl Database {
+    // create the file of the key, or replace it if there is already one
+    pub async fn set_key_file(
+        &self,
+        key: KeyId,
+        filename: impl AsRef<str>,
+        content_type: impl AsRef<str>,
+        content: &[u8],
+    ) -> Result<KeyFileId> {
+        let filename = filename.as_ref();
+        let content_type = content_type.as_ref();
+
+        let query = sqlx::query!(
+            "INSERT INTO keys_files ('keyID', 'filename', 'contentType', 'content') VALUES (?, ?, ?, ?) ON CONFLICT (keyID) DO UPDATE SET filename = excluded.filename, contentType = excluded.contentType, content = excluded.content RETURNING id",
+            key.0,
+            filename,
+            content_type,
+            content
+        )
+        .fetch_one(&self.inner)
+        .await?;
+
+        Ok(KeyFileId(query.id))
+    }
+
+    pub async fn fetch_key_file(&self, key: KeyId) -> Result<Option<TableKeysFiles>> {
+        sqlx::query!("SELECT * FROM keys_files WHERE keyID = ? LIMIT 1", key.0)
+            .fetch_optional(&self.inner)
+            .await
+            .map(|s| {
+                s.map(|s| TableKeysFiles {
+                    id: KeyFileId(s.id),
+                    key_id: KeyId(s.keyID),
+                    filename: s.filename,
+                    content_type: s.contentType,
+                    content: s.content,
+                })
+            })
+            .map_err(color_eyre::Report::from)
+    }
+
+    // same as `fetch_key_file`, without loading the content
+    pub async fn fetch_key_file_info(&self, key: KeyId) -> Result<Option<TableKeysFilesInfo>> {
+        sqlx::query!(
+            "SELECT id, keyID, filename, contentType, length(content) AS \"size!: i64\" FROM keys_files WHERE keyID = ? LIMIT 1",
+            key.0
+        )
+        .fetch_optional(&self.inner)
+        .await
+        .map(|s| {
+            s.map(|s| TableKeysFilesInfo {
+                id: KeyFileId(s.id),
+                key_id: KeyId(s.keyID),
+                filename: s.filename,
+                content_type: s.contentType,
+                size: s.size,
+            })
+        })
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn delete_file_with_key_id(&self, key: KeyId) -> Result<bool> {
+        sqlx::query!("DELETE FROM keys_files WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected() == 1)
+            .map_err(color_eyre::Report::from)
+    }
+}
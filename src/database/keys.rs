@@ -1,5 +1,7 @@
 use super::Database;
 use super::Date;
+use super::Metadata;
+use super::orgs::OrgId;
 
 use color_eyre::{Result, eyre::eyre};
 use futures::StreamExt;
@@ -10,25 +12,174 @@ use std::str::FromStr;
 
 super::defineID!(KeyId => "keys");
 
+/// What a key holds
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    /// A single string (the `apiKey` column)
+    #[default]
+    Text,
+    /// A set of named fields (the `keys_fields` table)
+    Fields,
+    /// A binary payload with a filename (the `keys_files` table)
+    File,
+    /// A managed asymmetric keypair, only the public half leaves akm (the `keys_versions` table)
+    Signing,
+    /// A managed data-encryption key, only usable through encrypt/decrypt (the `keys_versions` table)
+    Transit,
+    /// A managed SSH keypair, the private half only goes to linked clients (the `keys_versions` table)
+    Ssh,
+    /// A TOTP seed, only its current code leaves akm (the `keys_totp` table)
+    Totp,
+}
+
+impl KeyKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyKind::Text => "text",
+            KeyKind::Fields => "fields",
+            KeyKind::File => "file",
+            KeyKind::Signing => "signing",
+            KeyKind::Transit => "transit",
+            KeyKind::Ssh => "ssh",
+            KeyKind::Totp => "totp",
+        }
+    }
+}
+
+impl FromStr for KeyKind {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(KeyKind::Text),
+            "fields" => Ok(KeyKind::Fields),
+            "file" => Ok(KeyKind::File),
+            "signing" => Ok(KeyKind::Signing),
+            "transit" => Ok(KeyKind::Transit),
+            "ssh" => Ok(KeyKind::Ssh),
+            "totp" => Ok(KeyKind::Totp),
+            _ => Err(eyre!("unknown key kind: {s}")),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TableKeys {
     pub id: KeyId,
+    pub org: OrgId,
+    pub namespace: String,
     pub name: String,
     pub description: String,
+    pub kind: KeyKind,
     pub key: Option<String>,
     pub rotate_at: Option<Date>,
     pub rotate_with: Option<String>,
+    /// Seconds, when set every retrieval grants a lease to renew within it
+    pub lease_ttl: Option<i64>,
+    /// A canary: any access to it raises an alert
+    pub honeytoken: bool,
+    /// Addresses the public JWKS of a signing key, see [`crate::signing::new_jwks_id`]
+    pub jwks_id: Option<String>,
+    pub metadata: Metadata,
+}
+
+// raw row of the `keys` table, as returned by `SELECT keys.*`
+#[allow(non_snake_case)]
+struct RowKeys {
+    id: i64,
+    orgID: i64,
+    namespace: String,
+    name: String,
+    description: String,
+    kind: String,
+    apiKey: Option<String>,
+    rotateAt: Option<String>,
+    rotateWith: Option<String>,
+    leaseTtl: Option<i64>,
+    honeytoken: bool,
+    jwksId: Option<String>,
+    tags: String,
+    owner: Option<String>,
+    contact: Option<String>,
+    provider: Option<String>,
+    runbook: Option<String>,
+    environment: Option<String>,
+}
+
+impl TryFrom<RowKeys> for TableKeys {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowKeys) -> Result<Self> {
+        Ok(TableKeys {
+            id: KeyId(r.id),
+            org: OrgId(r.orgID),
+            namespace: r.namespace,
+            name: r.name,
+            description: r.description,
+            kind: KeyKind::from_str(&r.kind)?,
+            key: r.apiKey,
+            rotate_at: r.rotateAt.map(|s| Date::from_str(&s)).transpose()?,
+            rotate_with: r.rotateWith,
+            lease_ttl: r.leaseTtl,
+            honeytoken: r.honeytoken,
+            jwks_id: r.jwksId,
+            metadata: Metadata {
+                tags: Metadata::tags_from_db(&r.tags)?,
+                owner: r.owner,
+                contact: r.contact,
+                provider: r.provider,
+                runbook: r.runbook,
+                environment: r.environment,
+            },
+        })
+    }
+}
+
+/// What `update_key` changes, the namespace, metadata and honeytoken flag are kept when `None`
+pub struct KeyUpdate<'a> {
+    pub namespace: Option<&'a str>,
+    pub name: &'a str,
+    pub desc: &'a str,
+    pub metadata: Option<&'a Metadata>,
+    pub honeytoken: Option<bool>,
+}
+
+/// Why `update_key` left a key untouched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyUpdateConflict {
+    /// It is linked to clients outside of its new namespace
+    LinkedOutsideNamespace,
+    /// Another key of the namespace has the name
+    NameTaken,
 }
 
 impl Database {
+    // returns `None` if a key with the same name already exists in the namespace
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_key(
         &self,
+        org: OrgId,
+        namespace: impl AsRef<str>,
         name: impl AsRef<str>,
         desc: impl AsRef<str>,
+        kind: KeyKind,
         key: Option<String>,
         update_at: Option<Date>,
         update_with: Option<String>,
-    ) -> Result<KeyId> {
+    ) -> Result<Option<KeyId>> {
+        let namespace = namespace.as_ref();
         let name = name.as_ref();
         let desc = desc.as_ref();
 
@@ -36,38 +187,83 @@ impl Database {
 
         let u_at = update_at.map(|d| d.to_string());
         let u_with = update_with.as_deref();
+        let jwks_id = (kind == KeyKind::Signing).then(crate::signing::new_jwks_id);
+        let kind = kind.as_str();
 
         let query = sqlx::query!(
-            "INSERT INTO keys ('name', 'description', 'apiKey', 'rotateAt', 'rotateWith') VALUES (?, ?, ?, ?, ?) RETURNING id",
+            "INSERT INTO keys ('orgID', 'namespace', 'name', 'description', 'kind', 'apiKey', 'rotateAt', 'rotateWith', 'jwksId') VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+            org.0,
+            namespace,
             name,
             desc,
+            kind,
             k,
             u_at,
-            u_with
-        ).fetch_one(&self.inner).await?;
+            u_with,
+            jwks_id
+        ).fetch_one(&self.inner).await;
 
-        Ok(KeyId(query.id))
+        match query {
+            Ok(query) => Ok(Some(KeyId(query.id))),
+            Err(e) if super::is_unique_violation(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn fetch_key(&self, key: KeyId) -> Result<Option<TableKeys>> {
-        let query = sqlx::query!("SELECT * FROM keys WHERE id = ? LIMIT 1", key.0)
-            .fetch_optional(&self.inner)
-            .await?;
+    pub async fn fetch_key(&self, org: OrgId, key: KeyId) -> Result<Option<TableKeys>> {
+        sqlx::query_as!(
+            RowKeys,
+            "SELECT * FROM keys WHERE id = ? AND orgID = ? LIMIT 1",
+            key.0,
+            org.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeys::try_from)
+        .transpose()
+    }
 
-        query
-            .map(|mut s| {
-                Ok(TableKeys {
-                    id: KeyId(s.id),
-                    name: s.name,
-                    description: s.description,
-                    key: s.apiKey,
-                    rotate_at: s.rotateAt.map(|s| Date::from_str(&s)).transpose()?,
-                    rotate_with: s.rotateWith,
-                })
-            })
+    // not scoped to an org, only for endpoints exposing public data (like the JWKS)
+    pub async fn fetch_key_by_id(&self, key: KeyId) -> Result<Option<TableKeys>> {
+        sqlx::query_as!(RowKeys, "SELECT * FROM keys WHERE id = ? LIMIT 1", key.0)
+            .fetch_optional(&self.inner)
+            .await?
+            .map(TableKeys::try_from)
             .transpose()
     }
 
+    pub async fn fetch_key_by_jwks_id(
+        &self,
+        jwks_id: impl AsRef<str>,
+    ) -> Result<Option<TableKeys>> {
+        let jwks_id = jwks_id.as_ref();
+        sqlx::query_as!(
+            RowKeys,
+            "SELECT * FROM keys WHERE jwksId = ? LIMIT 1",
+            jwks_id
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeys::try_from)
+        .transpose()
+    }
+
+    // the key of a client<->key link, the link being enough to know the org
+    pub async fn fetch_linked_key(
+        &self,
+        link: super::clientkeys::ClientKeyId,
+    ) -> Result<Option<TableKeys>> {
+        sqlx::query_as!(
+            RowKeys,
+            "SELECT keys.* FROM keys INNER JOIN clients_key ON clients_key.keyID == keys.id WHERE clients_key.id = ? LIMIT 1",
+            link.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeys::try_from)
+        .transpose()
+    }
+
     pub async fn remove_key(&self, key: KeyId) -> Result<bool> {
         sqlx::query!("DELETE FROM keys WHERE id = ?", key.0)
             .execute(&self.inner)
@@ -79,42 +275,95 @@ impl Database {
 
     pub async fn get_all_keys_from_client(
         &self,
+        org: OrgId,
         client: super::clients::ClientId,
     ) -> Result<Vec<super::keys::TableKeys>> {
-        sqlx::query!(
-            "SELECT keys.* FROM keys INNER JOIN clients_key ON clients_key.keyID == keys.id WHERE clients_key.clientID = ?",
-            client.0
-        ).fetch_all(&self.inner)
+        sqlx::query_as!(
+            RowKeys,
+            "SELECT keys.* FROM keys INNER JOIN clients_key ON clients_key.keyID == keys.id WHERE clients_key.clientID = ? AND keys.orgID = ?",
+            client.0,
+            org.0
+        )
+        .fetch_all(&self.inner)
         .await
-        .map_err(color_eyre::Report::from)
-        .map(|v| v.into_iter().map(|r| Ok(TableKeys {
-            id: KeyId(r.id),
-            name: r.name,
-            description: r.description,
-            key: r.apiKey,
-            rotate_at: r.rotateAt.map(|s| Date::from_str(&s)).transpose()?,
-            rotate_with: r.rotateWith,
-        })).collect::<Result<Vec<_>>>())?
+        .map_err(color_eyre::Report::from)?
+        .into_iter()
+        .map(TableKeys::try_from)
+        .collect()
     }
 
-    pub async fn update_key_info(
+    // everything is written at once, or nothing when it conflicts
+    pub async fn update_key(
         &self,
         key: KeyId,
-        name: impl AsRef<str>,
-        desc: impl AsRef<str>,
-    ) -> Result<()> {
-        let name = name.as_ref();
-        let desc = desc.as_ref();
-        sqlx::query!(
-            "UPDATE keys SET name = ?, description = ? WHERE id = ?",
-            name,
-            desc,
+        update: &KeyUpdate<'_>,
+    ) -> Result<Result<(), KeyUpdateConflict>> {
+        if update.metadata.is_some_and(|m| !m.is_valid_runbook()) {
+            return Err(eyre!("runbook isn't an http(s) URL"));
+        }
+        // writing first holds the write lock, so no link can be created before the check below
+        let mut tx = self.inner.begin().await?;
+        match sqlx::query!(
+            "UPDATE keys SET namespace = COALESCE(?, namespace), name = ?, description = ? WHERE id = ?",
+            update.namespace,
+            update.name,
+            update.desc,
             key.0
         )
-        .execute(&self.inner)
+        .execute(&mut *tx)
         .await
-        .map_err(color_eyre::Report::from)
-        .map(|_| ())
+        {
+            Ok(_) => (),
+            Err(e) if super::is_unique_violation(&e) => {
+                return Ok(Err(KeyUpdateConflict::NameTaken));
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if update.namespace.is_some()
+            && sqlx::query_scalar!(
+                "SELECT COUNT(*) FROM clients_key INNER JOIN clients ON clients.id == clients_key.clientID INNER JOIN keys ON keys.id == clients_key.keyID WHERE clients_key.keyID = ? AND clients.namespace != keys.namespace",
+                key.0
+            )
+            .fetch_one(&mut *tx)
+            .await?
+                != 0
+        {
+            return Ok(Err(KeyUpdateConflict::LinkedOutsideNamespace));
+        }
+        if let Some(metadata) = update.metadata {
+            let tags = metadata.tags_to_db();
+            sqlx::query!(
+                "UPDATE keys SET tags = ?, owner = ?, contact = ?, provider = ?, runbook = ?, environment = ? WHERE id = ?",
+                tags,
+                metadata.owner,
+                metadata.contact,
+                metadata.provider,
+                metadata.runbook,
+                metadata.environment,
+                key.0
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some(honeytoken) = update.honeytoken {
+            sqlx::query!(
+                "UPDATE keys SET honeytoken = ? WHERE id = ?",
+                honeytoken,
+                key.0
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(Ok(()))
+    }
+
+    pub async fn update_key_lease_ttl(&self, key: KeyId, ttl: Option<i64>) -> Result<()> {
+        sqlx::query!("UPDATE keys SET leaseTtl = ? WHERE id = ?", ttl, key.0)
+            .execute(&self.inner)
+            .await
+            .map_err(color_eyre::Report::from)
+            .map(|_| ())
     }
 
     pub async fn update_key_secrets(
@@ -155,24 +404,47 @@ impl Database {
         Ok(())
     }
 
-    pub async fn get_all_keys(&self) -> Result<Vec<TableKeys>> {
-        sqlx::query!("SELECT * FROM keys")
+    // every namespace used by at least a key or a client of the org
+    pub async fn get_all_namespaces(&self, org: OrgId) -> Result<Vec<String>> {
+        sqlx::query_scalar!(
+            "SELECT namespace FROM keys WHERE orgID = ? UNION SELECT namespace FROM clients WHERE orgID = ? ORDER BY namespace",
+            org.0,
+            org.0
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)
+    }
+
+    // every key of every org of one of `kind` that should have been rotated by `today`
+    pub async fn get_all_keys_to_rotate(
+        &self,
+        kind: KeyKind,
+        today: Date,
+    ) -> Result<Vec<TableKeys>> {
+        let kind = kind.as_str();
+        let today = today.to_string();
+        sqlx::query_as!(
+            RowKeys,
+            "SELECT * FROM keys WHERE kind = ? AND rotateAt IS NOT NULL AND rotateAt <= ?",
+            kind,
+            today
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)?
+        .into_iter()
+        .map(TableKeys::try_from)
+        .collect()
+    }
+
+    pub async fn get_all_keys(&self, org: OrgId) -> Result<Vec<TableKeys>> {
+        sqlx::query_as!(RowKeys, "SELECT * FROM keys WHERE orgID = ?", org.0)
             .fetch_all(&self.inner)
             .await
-            .map_err(color_eyre::Report::from)
-            .map(|v| {
-                v.into_iter()
-                    .map(|r| -> Result<_> {
-                        Ok(TableKeys {
-                            id: KeyId(r.id),
-                            name: r.name,
-                            description: r.description,
-                            key: r.apiKey,
-                            rotate_at: r.rotateAt.map(|s| Date::from_str(&s)).transpose()?,
-                            rotate_with: r.rotateWith,
-                        })
-                    })
-                    .collect::<Result<Vec<_>>>()
-            })?
+            .map_err(color_eyre::Report::from)?
+            .into_iter()
+            .map(TableKeys::try_from)
+            .collect()
     }
 }
@@ -0,0 +1,88 @@
+use super::Database;
+use super::keys::KeyId;
+use crate::totp::{TotpAlgorithm, TotpSeed};
+
+use color_eyre::{Result, eyre::eyre};
+use std::str::FromStr;
+
+super::defineID!(KeyTotpId => "keys_totp");
+
+#[derive(Debug, Clone)]
+pub struct TableKeysTotp {
+    pub id: KeyTotpId,
+    pub key_id: KeyId,
+    pub seed: TotpSeed,
+}
+
+// raw row of the `keys_totp` table, as returned by `SELECT keys_totp.*`
+#[allow(non_snake_case)]
+struct RowKeysTotp {
+    id: i64,
+    keyID: i64,
+    secret: String,
+    algorithm: String,
+    digits: i64,
+    period: i64,
+    issuer: Option<String>,
+    account: Option<String>,
+}
+
+impl TryFrom<RowKeysTotp> for TableKeysTotp {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowKeysTotp) -> Result<Self> {
+        Ok(TableKeysTotp {
+            id: KeyTotpId(r.id),
+            key_id: KeyId(r.keyID),
+            seed: TotpSeed {
+                secret: r.secret,
+                algorithm: TotpAlgorithm::from_str(&r.algorithm)?,
+                digits: r.digits.try_into()?,
+                period: r.period.try_into()?,
+                issuer: r.issuer,
+                account: r.account,
+            },
+        })
+    }
+}
+
+impl Database {
+    // set the seed of the key, replacing the previous one
+    pub async fn set_key_totp(&self, key: KeyId, seed: &TotpSeed) -> Result<KeyTotpId> {
+        let algorithm = seed.algorithm.as_str();
+        let query = sqlx::query!(
+            "INSERT INTO keys_totp ('keyID', 'secret', 'algorithm', 'digits', 'period', 'issuer', 'account') VALUES (?, ?, ?, ?, ?, ?, ?) ON CONFLICT (keyID) DO UPDATE SET secret = excluded.secret, algorithm = excluded.algorithm, digits = excluded.digits, period = excluded.period, issuer = excluded.issuer, account = excluded.account RETURNING id",
+            key.0,
+            seed.secret,
+            algorithm,
+            seed.digits,
+            seed.period,
+            seed.issuer,
+            seed.account
+        )
+        .fetch_one(&self.inner)
+        .await?;
+
+        Ok(KeyTotpId(query.id))
+    }
+
+    pub async fn fetch_key_totp(&self, key: KeyId) -> Result<Option<TableKeysTotp>> {
+        sqlx::query_as!(
+            RowKeysTotp,
+            "SELECT * FROM keys_totp WHERE keyID = ? LIMIT 1",
+            key.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeysTotp::try_from)
+        .transpose()
+    }
+
+    pub async fn delete_totp_with_key_id(&self, key: KeyId) -> Result<bool> {
+        sqlx::query!("DELETE FROM keys_totp WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected() == 1)
+            .map_err(color_eyre::Report::from)
+    }
+}
@@ -0,0 +1,151 @@
+use super::Database;
+use super::Date;
+use super::keys::KeyId;
+
+use color_eyre::{Result, eyre::eyre};
+use std::str::FromStr;
+
+super::defineID!(KeyVersionId => "keys_versions");
+
+#[derive(Debug, Clone)]
+pub struct TableKeysVersions {
+    pub id: KeyVersionId,
+    pub key_id: KeyId,
+    pub version: i64,
+    pub algorithm: String,
+    pub material: Vec<u8>,
+    pub created_at: Date,
+    pub retire_at: Option<Date>,
+}
+
+// raw row of the `keys_versions` table, as returned by `SELECT keys_versions.*`
+#[allow(non_snake_case)]
+struct RowKeysVersions {
+    id: i64,
+    keyID: i64,
+    version: i64,
+    algorithm: String,
+    material: Vec<u8>,
+    createdAt: String,
+    retireAt: Option<String>,
+}
+
+impl TryFrom<RowKeysVersions> for TableKeysVersions {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowKeysVersions) -> Result<Self> {
+        Ok(TableKeysVersions {
+            id: KeyVersionId(r.id),
+            key_id: KeyId(r.keyID),
+            version: r.version,
+            algorithm: r.algorithm,
+            material: r.material,
+            created_at: Date::from_str(&r.createdAt)?,
+            retire_at: r.retireAt.map(|s| Date::from_str(&s)).transpose()?,
+        })
+    }
+}
+
+impl Database {
+    // add a version after the current one, returns its number
+    pub async fn create_key_version(
+        &self,
+        key: KeyId,
+        algorithm: impl AsRef<str>,
+        material: &[u8],
+        today: Date,
+    ) -> Result<i64> {
+        let algorithm = algorithm.as_ref();
+        let today = today.to_string();
+
+        sqlx::query_scalar!(
+            "INSERT INTO keys_versions ('keyID', 'version', 'algorithm', 'material', 'createdAt') VALUES (?, (SELECT COALESCE(MAX(version), 0) + 1 FROM keys_versions WHERE keyID = ?), ?, ?, ?) RETURNING version",
+            key.0,
+            key.0,
+            algorithm,
+            material,
+            today
+        )
+        .fetch_one(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn fetch_current_key_version(&self, key: KeyId) -> Result<Option<TableKeysVersions>> {
+        sqlx::query_as!(
+            RowKeysVersions,
+            "SELECT * FROM keys_versions WHERE keyID = ? ORDER BY version DESC LIMIT 1",
+            key.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeysVersions::try_from)
+        .transpose()
+    }
+
+    pub async fn fetch_key_version(
+        &self,
+        key: KeyId,
+        version: i64,
+    ) -> Result<Option<TableKeysVersions>> {
+        sqlx::query_as!(
+            RowKeysVersions,
+            "SELECT * FROM keys_versions WHERE keyID = ? AND version = ? LIMIT 1",
+            key.0,
+            version
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableKeysVersions::try_from)
+        .transpose()
+    }
+
+    // versions still published at `today`, newest first
+    pub async fn get_live_key_versions(
+        &self,
+        key: KeyId,
+        today: Date,
+    ) -> Result<Vec<TableKeysVersions>> {
+        let today = today.to_string();
+        sqlx::query_as!(
+            RowKeysVersions,
+            "SELECT * FROM keys_versions WHERE keyID = ? AND (retireAt IS NULL OR retireAt > ?) ORDER BY version DESC",
+            key.0,
+            today
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)?
+        .into_iter()
+        .map(TableKeysVersions::try_from)
+        .collect()
+    }
+
+    // start the grace period of every version older than `version` not already retiring
+    pub async fn retire_key_versions_before(
+        &self,
+        key: KeyId,
+        version: i64,
+        retire_at: Date,
+    ) -> Result<u64> {
+        let retire_at = retire_at.to_string();
+        sqlx::query!(
+            "UPDATE keys_versions SET retireAt = ? WHERE keyID = ? AND version < ? AND retireAt IS NULL",
+            retire_at,
+            key.0,
+            version
+        )
+        .execute(&self.inner)
+        .await
+        .map(|s| s.rows_affected())
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn delete_all_versions_with_key_id(&self, key: KeyId) -> Result<u64> {
+        sqlx::query!("DELETE FROM keys_versions WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected())
+            .map_err(color_eyre::Report::from)
+    }
+}
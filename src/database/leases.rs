@@ -0,0 +1,166 @@
+use super::Database;
+use super::clientkeys::ClientKeyId;
+use super::clients::ClientId;
+use super::keys::KeyId;
+use super::{time_from_db, time_to_db};
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+
+super::defineID!(LeaseId => "leases");
+
+#[derive(Debug, Clone)]
+pub struct TableLeases {
+    pub id: LeaseId,
+    pub key_id: KeyId,
+    pub client_id: ClientId,
+    pub link_id: ClientKeyId,
+    pub lease: String,
+    /// Seconds, each renewal pushes the expiry back by it
+    pub ttl: i64,
+    pub granted_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl TableLeases {
+    /// Whether the client still holds a live copy of the key at `now`
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && now < self.expires_at
+    }
+}
+
+// raw row of the `leases` table, as returned by `SELECT leases.*`
+#[allow(non_snake_case)]
+struct RowLeases {
+    id: i64,
+    keyID: i64,
+    clientID: i64,
+    clientKeyID: i64,
+    lease: String,
+    ttl: i64,
+    grantedAt: String,
+    expiresAt: String,
+    revokedAt: Option<String>,
+}
+
+impl TryFrom<RowLeases> for TableLeases {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowLeases) -> Result<Self> {
+        Ok(TableLeases {
+            id: LeaseId(r.id),
+            key_id: KeyId(r.keyID),
+            client_id: ClientId(r.clientID),
+            link_id: ClientKeyId(r.clientKeyID),
+            lease: r.lease,
+            ttl: r.ttl,
+            granted_at: time_from_db(&r.grantedAt)?,
+            expires_at: time_from_db(&r.expiresAt)?,
+            revoked_at: r.revokedAt.as_deref().map(time_from_db).transpose()?,
+        })
+    }
+}
+
+impl Database {
+    /// Lease the key of `link` to its client for `ttl` seconds
+    pub async fn create_lease(
+        &self,
+        link: &super::clientkeys::TableClientsKey,
+        lease: impl AsRef<str>,
+        ttl: i64,
+    ) -> Result<TableLeases> {
+        let lease = lease.as_ref();
+        let now = Utc::now();
+        let granted_at = time_to_db(now);
+        let expires_at = time_to_db(now + chrono::TimeDelta::seconds(ttl));
+
+        sqlx::query_as!(
+            RowLeases,
+            "INSERT INTO leases ('keyID', 'clientID', 'clientKeyID', 'lease', 'ttl', 'grantedAt', 'expiresAt') VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING *",
+            link.key_id.0,
+            link.client_id.0,
+            link.id.0,
+            lease,
+            ttl,
+            granted_at,
+            expires_at,
+        )
+        .fetch_one(&self.inner)
+        .await?
+        .try_into()
+    }
+
+    // the lease, if it was granted through `link`
+    pub async fn fetch_lease(
+        &self,
+        link: ClientKeyId,
+        lease: impl AsRef<str>,
+    ) -> Result<Option<TableLeases>> {
+        let lease = lease.as_ref();
+        sqlx::query_as!(
+            RowLeases,
+            "SELECT * FROM leases WHERE lease = ? AND clientKeyID = ? LIMIT 1",
+            lease,
+            link.0
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableLeases::try_from)
+        .transpose()
+    }
+
+    // push a live lease back to its TTL from now, return false if it isn't live anymore
+    pub async fn renew_lease(&self, lease: &TableLeases) -> Result<bool> {
+        let now = Utc::now();
+        let expires_at = time_to_db(now + chrono::TimeDelta::seconds(lease.ttl));
+        let now = time_to_db(now);
+        sqlx::query!(
+            "UPDATE leases SET expiresAt = ? WHERE id = ? AND revokedAt IS NULL AND expiresAt > ?",
+            expires_at,
+            lease.id.0,
+            now
+        )
+        .execute(&self.inner)
+        .await
+        .map(|s| s.rows_affected() == 1)
+        .map_err(color_eyre::Report::from)
+    }
+
+    // every lease of the key, latest first
+    pub async fn get_all_leases_of_key(&self, key: KeyId) -> Result<Vec<TableLeases>> {
+        sqlx::query_as!(
+            RowLeases,
+            "SELECT * FROM leases WHERE keyID = ? ORDER BY id DESC",
+            key.0
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(TableLeases::try_from)
+        .collect()
+    }
+
+    // revoke the live leases of the key, return how many
+    pub async fn revoke_key_leases(&self, key: KeyId) -> Result<u64> {
+        let now = time_to_db(Utc::now());
+        sqlx::query!(
+            "UPDATE leases SET revokedAt = ? WHERE keyID = ? AND revokedAt IS NULL AND expiresAt > ?",
+            now,
+            key.0,
+            now
+        )
+        .execute(&self.inner)
+        .await
+        .map(|s| s.rows_affected())
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn delete_all_leases_with_key_id(&self, key: KeyId) -> Result<u64> {
+        sqlx::query!("DELETE FROM leases WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected())
+            .map_err(color_eyre::Report::from)
+    }
+}
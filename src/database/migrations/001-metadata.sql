@@ -0,0 +1,15 @@
+-- Keys and clients get their metadata
+
+ALTER TABLE clients ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE clients ADD COLUMN owner TEXT;
+ALTER TABLE clients ADD COLUMN contact TEXT;
+ALTER TABLE clients ADD COLUMN provider TEXT;
+ALTER TABLE clients ADD COLUMN runbook TEXT;
+ALTER TABLE clients ADD COLUMN environment TEXT;
+
+ALTER TABLE keys ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE keys ADD COLUMN owner TEXT;
+ALTER TABLE keys ADD COLUMN contact TEXT;
+ALTER TABLE keys ADD COLUMN provider TEXT;
+ALTER TABLE keys ADD COLUMN runbook TEXT;
+ALTER TABLE keys ADD COLUMN environment TEXT;
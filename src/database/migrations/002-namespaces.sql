@@ -0,0 +1,63 @@
+-- Every key and client goes to the `default` namespace, `clients`/`keys` are rebuilt as it comes
+-- before their name and key names become unique within a namespace.
+-- Runs with foreign keys off, inside a transaction.
+
+CREATE TABLE clients_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT
+);
+INSERT INTO clients_new ('id', 'name', 'description', 'tags', 'owner', 'contact', 'provider', 'runbook', 'environment')
+  SELECT id, name, description, tags, owner, contact, provider, runbook, environment FROM clients;
+DROP TABLE clients;
+ALTER TABLE clients_new RENAME TO clients;
+
+CREATE TABLE keys_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  apiKey TEXT,
+  rotateAt TEXT,
+  rotateWith TEXT,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  --
+  UNIQUE (namespace, name)
+);
+-- names weren't unique before, later duplicates get their id appended, and a random suffix on top
+-- when another key already has that name (keys `k`, `k` and `k-2`)
+INSERT INTO keys_new ('id', 'name', 'description', 'apiKey', 'rotateAt', 'rotateWith', 'tags', 'owner', 'contact', 'provider', 'runbook', 'environment')
+  SELECT
+    keys.id,
+    CASE
+      WHEN NOT EXISTS (SELECT 1 FROM keys AS k WHERE k.name = keys.name AND k.id < keys.id)
+      THEN keys.name
+      WHEN NOT EXISTS (SELECT 1 FROM keys AS k WHERE k.name = keys.name || '-' || keys.id)
+      THEN keys.name || '-' || keys.id
+      ELSE keys.name || '-' || keys.id || '-' || lower(hex(randomblob(8)))
+    END,
+    keys.description,
+    keys.apiKey,
+    keys.rotateAt,
+    keys.rotateWith,
+    keys.tags,
+    keys.owner,
+    keys.contact,
+    keys.provider,
+    keys.runbook,
+    keys.environment
+  FROM keys;
+DROP TABLE keys;
+ALTER TABLE keys_new RENAME TO keys;
@@ -0,0 +1,56 @@
+-- Every key and client goes to a `default` org of which every user is a member, and
+-- `clients`/`keys` are rebuilt for their new constraints.
+-- Runs with foreign keys off, inside a transaction.
+
+INSERT OR IGNORE INTO orgs ('name') VALUES ('default');
+INSERT OR IGNORE INTO orgs_members ('orgID', 'userID')
+  SELECT orgs.id, users.id FROM orgs, users WHERE orgs.name = 'default';
+
+CREATE TABLE clients_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO clients_new
+  SELECT clients.id, orgs.id, clients.namespace, clients.name, clients.description, clients.tags,
+    clients.owner, clients.contact, clients.provider, clients.runbook, clients.environment
+  FROM clients, orgs WHERE orgs.name = 'default';
+DROP TABLE clients;
+ALTER TABLE clients_new RENAME TO clients;
+
+CREATE TABLE keys_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  apiKey TEXT,
+  rotateAt TEXT,
+  rotateWith TEXT,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  --
+  UNIQUE (orgID, namespace, name),
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO keys_new
+  SELECT keys.id, orgs.id, keys.namespace, keys.name, keys.description, keys.apiKey, keys.rotateAt,
+    keys.rotateWith, keys.tags, keys.owner, keys.contact, keys.provider, keys.runbook,
+    keys.environment
+  FROM keys, orgs WHERE orgs.name = 'default';
+DROP TABLE keys;
+ALTER TABLE keys_new RENAME TO keys;
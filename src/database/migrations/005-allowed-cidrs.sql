@@ -0,0 +1,4 @@
+-- Clients and their links can be used from anywhere until restricted
+
+ALTER TABLE clients ADD COLUMN allowedCidrs TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE clients_key ADD COLUMN allowedCidrs TEXT NOT NULL DEFAULT '[]';
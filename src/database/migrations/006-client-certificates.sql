@@ -0,0 +1,25 @@
+-- `ALTER TABLE` can't add `UNIQUE` columns, `clients` is rebuilt to get the certificates.
+-- Runs with foreign keys off, inside a transaction.
+
+CREATE TABLE clients_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  allowedCidrs TEXT NOT NULL DEFAULT '[]',
+  tlsFingerprint TEXT UNIQUE,
+  tlsSan TEXT UNIQUE,
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO clients_new ('id', 'orgID', 'namespace', 'name', 'description', 'tags', 'owner', 'contact', 'provider', 'runbook', 'environment', 'allowedCidrs')
+  SELECT id, orgID, namespace, name, description, tags, owner, contact, provider, runbook, environment, allowedCidrs FROM clients;
+DROP TABLE clients;
+ALTER TABLE clients_new RENAME TO clients;
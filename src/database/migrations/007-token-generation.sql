@@ -0,0 +1,3 @@
+-- No access token was issued yet
+
+ALTER TABLE clients ADD COLUMN tokenGeneration INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Existing links stay valid forever, with unlimited uses
+
+ALTER TABLE clients_key ADD COLUMN validFrom TEXT;
+ALTER TABLE clients_key ADD COLUMN expiresAt TEXT;
+ALTER TABLE clients_key ADD COLUMN maxUses INTEGER;
+ALTER TABLE clients_key ADD COLUMN uses INTEGER NOT NULL DEFAULT 0;
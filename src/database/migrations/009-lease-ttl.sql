@@ -0,0 +1,28 @@
+-- Existing keys aren't leased, `keys` is rebuilt as `leaseTtl` comes before their metadata.
+-- Runs with foreign keys off, inside a transaction.
+
+CREATE TABLE keys_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  kind TEXT NOT NULL DEFAULT 'text',
+  apiKey TEXT,
+  rotateAt TEXT,
+  rotateWith TEXT,
+  leaseTtl INTEGER,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  --
+  UNIQUE (orgID, namespace, name),
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO keys_new ('id', 'orgID', 'namespace', 'name', 'description', 'kind', 'apiKey', 'rotateAt', 'rotateWith', 'tags', 'owner', 'contact', 'provider', 'runbook', 'environment')
+  SELECT id, orgID, namespace, name, description, kind, apiKey, rotateAt, rotateWith, tags, owner, contact, provider, runbook, environment FROM keys;
+DROP TABLE keys;
+ALTER TABLE keys_new RENAME TO keys;
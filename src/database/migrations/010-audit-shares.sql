@@ -0,0 +1,20 @@
+-- `audit_log` is rebuilt as `shareID` comes before the action. It may have just been created by
+-- `init.sql` with the column already, so only the older ones are copied.
+-- Runs with foreign keys off, inside a transaction.
+
+CREATE TABLE audit_log_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  keyID INTEGER,
+  userID INTEGER,
+  clientID INTEGER,
+  shareID INTEGER,
+  action TEXT NOT NULL,
+  at TEXT NOT NULL,
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO audit_log_new ('id', 'orgID', 'keyID', 'userID', 'clientID', 'action', 'at')
+  SELECT id, orgID, keyID, userID, clientID, action, at FROM audit_log;
+DROP TABLE audit_log;
+ALTER TABLE audit_log_new RENAME TO audit_log;
@@ -0,0 +1,49 @@
+-- Existing keys aren't honeytokens and existing audit entries are `info`, `keys` and `audit_log`
+-- are rebuilt as the new columns come before others. `audit_log` may have just been created by
+-- `init.sql` with its column already, so only the older ones are copied.
+-- Runs with foreign keys off, inside a transaction.
+
+CREATE TABLE keys_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  kind TEXT NOT NULL DEFAULT 'text',
+  apiKey TEXT,
+  rotateAt TEXT,
+  rotateWith TEXT,
+  leaseTtl INTEGER,
+  honeytoken BOOLEAN NOT NULL DEFAULT FALSE,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  --
+  UNIQUE (orgID, namespace, name),
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO keys_new ('id', 'orgID', 'namespace', 'name', 'description', 'kind', 'apiKey', 'rotateAt', 'rotateWith', 'leaseTtl', 'tags', 'owner', 'contact', 'provider', 'runbook', 'environment')
+  SELECT id, orgID, namespace, name, description, kind, apiKey, rotateAt, rotateWith, leaseTtl, tags, owner, contact, provider, runbook, environment FROM keys;
+DROP TABLE keys;
+ALTER TABLE keys_new RENAME TO keys;
+
+CREATE TABLE audit_log_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  keyID INTEGER,
+  userID INTEGER,
+  clientID INTEGER,
+  shareID INTEGER,
+  action TEXT NOT NULL,
+  severity TEXT NOT NULL DEFAULT 'info',
+  at TEXT NOT NULL,
+  --
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO audit_log_new ('id', 'orgID', 'keyID', 'userID', 'clientID', 'shareID', 'action', 'at')
+  SELECT id, orgID, keyID, userID, clientID, shareID, action, at FROM audit_log;
+DROP TABLE audit_log;
+ALTER TABLE audit_log_new RENAME TO audit_log;
@@ -0,0 +1,5 @@
+-- Signing keys were addressed by their id on the public JWKS endpoint, they get a random one
+
+ALTER TABLE keys ADD COLUMN jwksId TEXT;
+CREATE UNIQUE INDEX keys_jwksId ON keys (jwksId);
+UPDATE keys SET jwksId = lower(hex(randomblob(16))) WHERE kind = 'signing';
@@ -0,0 +1,27 @@
+-- Client certificates were unique across orgs, which told an org which ones the others use:
+-- `clients` is rebuilt to make them unique within an org
+
+CREATE TABLE clients_new (
+  id INTEGER NOT NULL PRIMARY KEY ASC AUTOINCREMENT,
+  orgID INTEGER NOT NULL,
+  namespace TEXT NOT NULL DEFAULT 'default',
+  name TEXT NOT NULL,
+  description TEXT NOT NULL,
+  tags TEXT NOT NULL DEFAULT '[]',
+  owner TEXT,
+  contact TEXT,
+  provider TEXT,
+  runbook TEXT,
+  environment TEXT,
+  allowedCidrs TEXT NOT NULL DEFAULT '[]',
+  tlsFingerprint TEXT,
+  tlsSan TEXT,
+  tokenGeneration INTEGER NOT NULL DEFAULT 0,
+  --
+  UNIQUE (orgID, tlsFingerprint),
+  UNIQUE (orgID, tlsSan),
+  FOREIGN KEY (orgID) REFERENCES orgs (id)
+);
+INSERT INTO clients_new SELECT * FROM clients;
+DROP TABLE clients;
+ALTER TABLE clients_new RENAME TO clients;
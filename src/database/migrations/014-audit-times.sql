@@ -0,0 +1,4 @@
+-- Audit entries were timestamped with nanoseconds and `+00:00`, they get the format of every
+-- other RFC 3339 column (UTC, whole seconds)
+
+UPDATE audit_log SET at = strftime('%Y-%m-%dT%H:%M:%SZ', at);
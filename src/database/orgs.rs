@@ -0,0 +1,123 @@
+use super::Database;
+use super::users::{TableUsers, UserId};
+
+use color_eyre::{Result, eyre::eyre};
+
+super::defineID!(OrgId => "orgs");
+
+#[derive(Debug, Clone)]
+pub struct TableOrgs {
+    pub id: OrgId,
+    pub name: String,
+}
+
+impl Database {
+    // returns `None` if an org with the same name already exists
+    pub async fn create_org(&self, name: impl AsRef<str>) -> Result<Option<OrgId>> {
+        let name = name.as_ref();
+        match sqlx::query!("INSERT INTO orgs ('name') VALUES (?) RETURNING id", name)
+            .fetch_one(&self.inner)
+            .await
+        {
+            Ok(query) => Ok(Some(OrgId(query.id))),
+            Err(e) if super::is_unique_violation(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn fetch_org(&self, org: OrgId) -> Result<Option<TableOrgs>> {
+        sqlx::query!("SELECT * FROM orgs WHERE id = ? LIMIT 1", org.0)
+            .fetch_optional(&self.inner)
+            .await
+            .map(|s| {
+                s.map(|s| TableOrgs {
+                    id: OrgId(s.id),
+                    name: s.name,
+                })
+            })
+            .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn get_all_orgs_from_user(&self, user: UserId) -> Result<Vec<TableOrgs>> {
+        sqlx::query!(
+            "SELECT orgs.* FROM orgs INNER JOIN orgs_members ON orgs_members.orgID == orgs.id WHERE orgs_members.userID = ? ORDER BY orgs.id",
+            user.0
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map(|v| {
+            v.into_iter()
+                .map(|s| TableOrgs {
+                    id: OrgId(s.id),
+                    name: s.name,
+                })
+                .collect()
+        })
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn get_all_org_members(&self, org: OrgId) -> Result<Vec<TableUsers>> {
+        sqlx::query!(
+            "SELECT users.id AS \"id!\", users.name, users.token FROM users INNER JOIN orgs_members ON orgs_members.userID == users.id WHERE orgs_members.orgID = ? ORDER BY users.id",
+            org.0
+        )
+        .fetch_all(&self.inner)
+        .await
+        .map(|v| {
+            v.into_iter()
+                .map(|s| TableUsers {
+                    id: UserId(s.id),
+                    name: s.name,
+                    token: s.token,
+                })
+                .collect()
+        })
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn is_org_member(&self, org: OrgId, user: UserId) -> Result<bool> {
+        sqlx::query!(
+            "SELECT id FROM orgs_members WHERE orgID = ? AND userID = ? LIMIT 1",
+            org.0,
+            user.0
+        )
+        .fetch_optional(&self.inner)
+        .await
+        .map(|s| s.is_some())
+        .map_err(color_eyre::Report::from)
+    }
+
+    // returns false if the user was already a member
+    pub async fn add_org_member(&self, org: OrgId, user: UserId) -> Result<bool> {
+        match sqlx::query!(
+            "INSERT INTO orgs_members ('orgID', 'userID') VALUES (?, ?)",
+            org.0,
+            user.0
+        )
+        .execute(&self.inner)
+        .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if super::is_unique_violation(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn remove_org_member(&self, org: OrgId, user: UserId) -> Result<bool> {
+        sqlx::query!(
+            "DELETE FROM orgs_members WHERE orgID = ? AND userID = ?",
+            org.0,
+            user.0
+        )
+        .execute(&self.inner)
+        .await
+        .inspect(|s| {
+            assert!(
+                s.rows_affected() <= 1,
+                "mutliple membership for the same user"
+            )
+        })
+        .map(|s| s.rows_affected() == 1)
+        .map_err(color_eyre::Report::from)
+    }
+}
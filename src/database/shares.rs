@@ -0,0 +1,162 @@
+use super::Database;
+use super::keys::KeyId;
+use super::orgs::OrgId;
+use super::users::UserId;
+use super::{time_from_db, time_to_db};
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+
+super::defineID!(ShareId => "shares");
+
+#[derive(Debug, Clone)]
+pub struct TableShares {
+    pub id: ShareId,
+    pub org: OrgId,
+    pub key_id: KeyId,
+    pub user_id: UserId,
+    pub token: String,
+    /// See [`crate::share::hash_passphrase`]
+    pub passphrase: Option<String>,
+    pub failed_attempts: i64,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub burned_at: Option<DateTime<Utc>>,
+}
+
+impl TableShares {
+    /// Whether the link can still be opened at `now`
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        self.burned_at.is_none() && now < self.expires_at
+    }
+}
+
+// raw row of the `shares` table, as returned by `SELECT shares.*`
+#[allow(non_snake_case)]
+struct RowShares {
+    id: i64,
+    orgID: i64,
+    keyID: i64,
+    userID: i64,
+    token: String,
+    passphrase: Option<String>,
+    failedAttempts: i64,
+    createdAt: String,
+    expiresAt: String,
+    burnedAt: Option<String>,
+}
+
+impl TryFrom<RowShares> for TableShares {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowShares) -> Result<Self> {
+        Ok(TableShares {
+            id: ShareId(r.id),
+            org: OrgId(r.orgID),
+            key_id: KeyId(r.keyID),
+            user_id: UserId(r.userID),
+            token: r.token,
+            passphrase: r.passphrase,
+            failed_attempts: r.failedAttempts,
+            created_at: time_from_db(&r.createdAt)?,
+            expires_at: time_from_db(&r.expiresAt)?,
+            burned_at: r.burnedAt.as_deref().map(time_from_db).transpose()?,
+        })
+    }
+}
+
+impl Database {
+    pub async fn create_share(
+        &self,
+        org: OrgId,
+        key: KeyId,
+        user: UserId,
+        token: impl AsRef<str>,
+        passphrase: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<TableShares> {
+        let token = token.as_ref();
+        let created_at = time_to_db(Utc::now());
+        let expires_at = time_to_db(expires_at);
+
+        sqlx::query_as!(
+            RowShares,
+            "INSERT INTO shares ('orgID', 'keyID', 'userID', 'token', 'passphrase', 'createdAt', 'expiresAt') VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING *",
+            org.0,
+            key.0,
+            user.0,
+            token,
+            passphrase,
+            created_at,
+            expires_at,
+        )
+        .fetch_one(&self.inner)
+        .await?
+        .try_into()
+    }
+
+    pub async fn fetch_share_by_token(
+        &self,
+        token: impl AsRef<str>,
+    ) -> Result<Option<TableShares>> {
+        let token = token.as_ref();
+        sqlx::query_as!(
+            RowShares,
+            "SELECT * FROM shares WHERE token = ? LIMIT 1",
+            token
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableShares::try_from)
+        .transpose()
+    }
+
+    // latest first
+    pub async fn get_all_shares_of_key(&self, key: KeyId) -> Result<Vec<TableShares>> {
+        sqlx::query_as!(
+            RowShares,
+            "SELECT * FROM shares WHERE keyID = ? ORDER BY id DESC",
+            key.0
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(TableShares::try_from)
+        .collect()
+    }
+
+    // burn the link if it can still be opened, return false if it couldn't anymore, so only
+    // one of concurrent openings gets the value
+    pub async fn burn_share(&self, share: ShareId) -> Result<bool> {
+        let now = time_to_db(Utc::now());
+        sqlx::query!(
+            "UPDATE shares SET burnedAt = ? WHERE id = ? AND burnedAt IS NULL AND expiresAt > ?",
+            now,
+            share.0,
+            now
+        )
+        .execute(&self.inner)
+        .await
+        .map(|s| s.rows_affected() == 1)
+        .map_err(color_eyre::Report::from)
+    }
+
+    // count a wrong passphrase, return how many were tried so far
+    pub async fn record_share_failure(&self, share: ShareId) -> Result<i64> {
+        sqlx::query_scalar!(
+            "UPDATE shares SET failedAttempts = failedAttempts + 1 WHERE id = ? RETURNING failedAttempts",
+            share.0
+        )
+        .fetch_one(&self.inner)
+        .await
+        .map_err(color_eyre::Report::from)
+    }
+
+    pub async fn delete_all_shares_with_key_id(&self, key: KeyId) -> Result<u64> {
+        sqlx::query!("DELETE FROM shares WHERE keyID = ?", key.0)
+            .execute(&self.inner)
+            .await
+            .map(|s| s.rows_affected())
+            .map_err(color_eyre::Report::from)
+    }
+}
@@ -0,0 +1,124 @@
+use super::Database;
+use super::clientkeys::{ClientKeyId, TableClientsKey};
+use super::clients::ClientId;
+use super::keys::KeyId;
+use super::orgs::OrgId;
+use super::users::UserId;
+use super::{time_from_db, time_to_db};
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+
+super::defineID!(WrapId => "wraps");
+
+#[derive(Debug, Clone)]
+pub struct TableWraps {
+    pub id: WrapId,
+    pub org: OrgId,
+    pub key_id: KeyId,
+    pub client_id: ClientId,
+    pub link_id: ClientKeyId,
+    pub user_id: UserId,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub unwrapped_at: Option<DateTime<Utc>>,
+    pub unwrapped_from: Option<String>,
+}
+
+// raw row of the `wraps` table, as returned by `SELECT wraps.*`
+#[allow(non_snake_case)]
+struct RowWraps {
+    id: i64,
+    orgID: i64,
+    keyID: i64,
+    clientID: i64,
+    clientKeyID: i64,
+    userID: i64,
+    token: String,
+    createdAt: String,
+    expiresAt: String,
+    unwrappedAt: Option<String>,
+    unwrappedFrom: Option<String>,
+}
+
+impl TryFrom<RowWraps> for TableWraps {
+    type Error = color_eyre::Report;
+
+    fn try_from(r: RowWraps) -> Result<Self> {
+        Ok(TableWraps {
+            id: WrapId(r.id),
+            org: OrgId(r.orgID),
+            key_id: KeyId(r.keyID),
+            client_id: ClientId(r.clientID),
+            link_id: ClientKeyId(r.clientKeyID),
+            user_id: UserId(r.userID),
+            token: r.token,
+            created_at: time_from_db(&r.createdAt)?,
+            expires_at: time_from_db(&r.expiresAt)?,
+            unwrapped_at: r.unwrappedAt.as_deref().map(time_from_db).transpose()?,
+            unwrapped_from: r.unwrappedFrom,
+        })
+    }
+}
+
+impl Database {
+    pub async fn create_wrap(
+        &self,
+        org: OrgId,
+        link: &TableClientsKey,
+        user: UserId,
+        token: impl AsRef<str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<TableWraps> {
+        let token = token.as_ref();
+        let created_at = time_to_db(Utc::now());
+        let expires_at = time_to_db(expires_at);
+
+        sqlx::query_as!(
+            RowWraps,
+            "INSERT INTO wraps ('orgID', 'keyID', 'clientID', 'clientKeyID', 'userID', 'token', 'createdAt', 'expiresAt') VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+            org.0,
+            link.key_id.0,
+            link.client_id.0,
+            link.id.0,
+            user.0,
+            token,
+            created_at,
+            expires_at,
+        )
+        .fetch_one(&self.inner)
+        .await?
+        .try_into()
+    }
+
+    pub async fn fetch_wrap_by_token(&self, token: impl AsRef<str>) -> Result<Option<TableWraps>> {
+        let token = token.as_ref();
+        sqlx::query_as!(
+            RowWraps,
+            "SELECT * FROM wraps WHERE token = ? LIMIT 1",
+            token
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(TableWraps::try_from)
+        .transpose()
+    }
+
+    // mark the token unwrapped if it still can be, return false if it couldn't anymore, so only
+    // one of concurrent unwraps gets the secret
+    pub async fn unwrap_wrap(&self, wrap: WrapId, from: Option<String>) -> Result<bool> {
+        let now = time_to_db(Utc::now());
+        sqlx::query!(
+            "UPDATE wraps SET unwrappedAt = ?, unwrappedFrom = ? WHERE id = ? AND unwrappedAt IS NULL AND expiresAt > ?",
+            now,
+            from,
+            wrap.0,
+            now
+        )
+        .execute(&self.inner)
+        .await
+        .map(|s| s.rows_affected() == 1)
+        .map_err(color_eyre::Report::from)
+    }
+}
@@ -1,22 +1,34 @@
-use axum::{extract::State, http::StatusCode, response::Html};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
 use serde::{Deserialize, Serialize};
 use tracing::*;
 
-use crate::api::ErrorToStatusCode as _;
+use crate::{api::ErrorToStatusCode as _, api::utils::MetadataFilter, database::Metadata};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ClientInfo {
     id: i64,
+    namespace: String,
     name: String,
     description: String,
+    metadata: Metadata,
+    allowed_cidrs: Vec<ipnet::IpNet>,
+    certificate: crate::database::clients::ClientCertificate,
 }
 
 impl From<crate::database::clients::TableClients> for ClientInfo {
     fn from(value: crate::database::clients::TableClients) -> Self {
         Self {
             id: value.id.inner(),
+            namespace: value.namespace,
             name: value.name,
             description: value.description,
+            metadata: value.metadata,
+            allowed_cidrs: value.allowed_cidrs,
+            certificate: value.certificate,
         }
     }
 }
@@ -25,30 +37,32 @@ impl From<crate::database::clients::TableClients> for ClientInfo {
 pub async fn get_all_clients(
     user: crate::auth::UserAuthRedirect,
     State(state): State<crate::AppState>,
+    Query(filter): Query<MetadataFilter>,
 ) -> Result<Html<String>, StatusCode> {
     let a = state
         .template_env
         .get_template("clients.html")
         .to_status()?;
-    let user: super::UserInfo = user
-        .get_user(&state.db)
-        .await
-        .to_status()?
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
-        .into();
+    let org = user.org();
+    let user = super::UserInfo::fetch(user, &state.db).await?;
 
     let all_clients = state
         .db
-        .get_all_clients()
+        .get_all_clients(org)
         .await
         .to_status()?
         .into_iter()
+        .filter(|c| filter.matches(&c.namespace, &c.name, &c.description, &c.metadata))
         .map(Into::into)
         .collect::<Vec<ClientInfo>>();
 
+    let namespaces = state.db.get_all_namespaces(org).await.to_status()?;
+
     a.render(serde_json::json!({
         "self": user,
         "clients": all_clients,
+        "filter": filter,
+        "namespaces": namespaces,
     }))
     .map(Html)
     .to_status()
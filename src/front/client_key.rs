@@ -18,6 +18,11 @@ struct AssocKeyInfo {
     k_desc: String,
     secret: String,
     last_used: Option<crate::database::Date>,
+    allowed_cidrs: Vec<ipnet::IpNet>,
+    limits: crate::database::clientkeys::LinkLimits,
+    uses: i64,
+    /// `pending`, `expired`, `used up`, or how long and how many uses are left
+    validity: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,20 +34,60 @@ struct KeyInfo {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ClientInfo {
     id: i64,
+    namespace: String,
     name: String,
     description: String,
+    allowed_cidrs: Vec<ipnet::IpNet>,
 }
 
 impl From<crate::database::clients::TableClients> for ClientInfo {
     fn from(value: crate::database::clients::TableClients) -> Self {
         Self {
             id: value.id.inner(),
+            namespace: value.namespace,
             name: value.name,
             description: value.description,
+            allowed_cidrs: value.allowed_cidrs,
         }
     }
 }
 
+// `2d 3h`, `3h 20m`, `12m`
+fn format_duration(d: chrono::TimeDelta) -> String {
+    let (days, hours, minutes) = (d.num_days(), d.num_hours() % 24, d.num_minutes() % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes.max(1)),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+fn validity(link: &crate::database::clientkeys::TableClientsKey) -> String {
+    let now = chrono::Utc::now();
+    let limits = &link.limits;
+    if let Some(from) = limits.valid_from.filter(|from| now < *from) {
+        return format!("pending, valid in {}", format_duration(from - now));
+    }
+    if limits.expires_at.is_some_and(|to| to <= now) {
+        return "expired".to_string();
+    }
+    if limits.max_uses.is_some_and(|max| link.uses >= max) {
+        return "used up".to_string();
+    }
+    let mut left = Vec::new();
+    if let Some(to) = limits.expires_at {
+        left.push(format!("{} left", format_duration(to - now)));
+    }
+    if let Some(max) = limits.max_uses {
+        left.push(format!("{} of {max} uses left", max - link.uses));
+    }
+    if left.is_empty() {
+        "unlimited".to_string()
+    } else {
+        left.join(", ")
+    }
+}
+
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 pub async fn get_client_key(
     user: crate::auth::UserAuthRedirect,
@@ -53,13 +98,9 @@ pub async fn get_client_key(
         .template_env
         .get_template("client_key.html")
         .to_status()?;
-    let user: super::UserInfo = user
-        .get_user(&state.db)
-        .await
-        .to_status()?
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
-        .into();
-    let client = crate::api::utils::client_from_raw(&state.db, cid)
+    let org = user.org();
+    let user = super::UserInfo::fetch(user, &state.db).await?;
+    let client = crate::api::utils::client_from_raw(&state.db, org, cid)
         .await
         .to_status()?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -67,7 +108,7 @@ pub async fn get_client_key(
 
     let all_clients_keys = state
         .db
-        .get_all_keys_from_client(client.id)
+        .get_all_keys_from_client(org, client.id)
         .await
         .to_status()?;
     let mut own_keys_info: Vec<AssocKeyInfo> = Vec::new();
@@ -76,7 +117,7 @@ pub async fn get_client_key(
     for k in all_clients_keys {
         let Some(k_associated) = state
             .db
-            .fetch_client_key_from_client_and_key(client_id, k.id)
+            .fetch_client_key_from_client_and_key(org, client_id, k.id)
             .await
             .to_status()?
         else {
@@ -88,6 +129,7 @@ pub async fn get_client_key(
             continue;
         };
         own_keys_id.insert(k.id.inner());
+        let validity = validity(&k_associated);
         own_keys_info.push(AssocKeyInfo {
             id: k_associated.id.inner(),
             k_name: k.name,
@@ -95,14 +137,20 @@ pub async fn get_client_key(
             k_id: k.id.inner(),
             secret: k_associated.secret,
             last_used: k_associated.last_used,
+            allowed_cidrs: k_associated.allowed_cidrs,
+            limits: k_associated.limits,
+            uses: k_associated.uses,
+            validity,
         })
     }
     let not_own_keys = state
         .db
-        .get_all_keys()
+        .get_all_keys(org)
         .await
         .to_status()?
         .into_iter()
+        // only keys of the same namespace can be linked to the client
+        .filter(|k| k.namespace == client.namespace)
         .map(|k| KeyInfo {
             id: k.id.inner(),
             name: k.name,
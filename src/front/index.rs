@@ -2,7 +2,16 @@ use axum::{extract::State, http::StatusCode, response::Html};
 use serde::{Deserialize, Serialize};
 use tracing::*;
 
-use crate::api::ErrorToStatusCode as _;
+use crate::{api::ErrorToStatusCode as _, certs::CertInfo, database::Date};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExpiringCert {
+    key_id: i64,
+    namespace: String,
+    name: String,
+    certificate: CertInfo,
+    days_left: i64,
+}
 
 #[cfg_attr(debug_assertions, axum::debug_handler)]
 pub async fn get_index(
@@ -11,15 +20,30 @@ pub async fn get_index(
 ) -> Result<Html<String>, StatusCode> {
     info!("Rendering index.html template");
     let a = state.template_env.get_template("index.html").to_status()?;
-    let user: super::UserInfo = user
-        .get_user(&state.db)
+    let org = user.org();
+    let user = super::UserInfo::fetch(user, &state.db).await?;
+
+    let today = chrono::Utc::now().date_naive();
+    let before = Date(today + chrono::Days::new(state.config.cert_expiry_days.into()));
+    let expiring = state
+        .db
+        .get_expiring_certs(org, before)
         .await
         .to_status()?
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
-        .into();
+        .into_iter()
+        .map(|(key, cert)| ExpiringCert {
+            key_id: key.id.inner(),
+            namespace: key.namespace,
+            name: key.name,
+            days_left: (cert.cert.not_after.0 - today).num_days(),
+            certificate: cert.cert,
+        })
+        .collect::<Vec<_>>();
 
     a.render(serde_json::json!({
         "self": user,
+        "expiring_certs": expiring,
+        "cert_expiry_days": state.config.cert_expiry_days,
     }))
     .map(Html)
     .to_status()
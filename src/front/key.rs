@@ -1,17 +1,59 @@
-use axum::{extract::State, http::StatusCode, response::Html};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
 use serde::{Deserialize, Serialize};
 use tracing::*;
 
-use crate::api::ErrorToStatusCode as _;
+use crate::{
+    api::ErrorToStatusCode as _,
+    api::utils::MetadataFilter,
+    certs::CertInfo,
+    database::{Metadata, keyfields::FieldKind, keys::KeyKind},
+};
+
+// values of `secret` fields are left out, the page reveals them through `key_secret`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFieldInfo {
+    name: String,
+    kind: FieldKind,
+    value: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    filename: String,
+    content_type: String,
+    size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyVersion {
+    kid: String,
+    algorithm: String,
+    created_at: crate::database::Date,
+    retire_at: Option<crate::database::Date>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct KeyInfo {
     id: i64,
+    namespace: String,
     name: String,
     description: String,
-    secret: String,
+    kind: KeyKind,
+    fields: Vec<KeyFieldInfo>,
+    file: Option<KeyFile>,
+    certificate: Option<CertInfo>,
+    versions: Vec<KeyVersion>,
+    ssh: Option<crate::ssh::SshPublicKey>,
+    totp: Option<crate::api::totp::TotpInfo>,
     rotate_at: Option<crate::database::Date>,
-    rotate_with: String,
+    lease_ttl: Option<i64>,
+    honeytoken: bool,
+    jwks_id: Option<String>,
+    metadata: Metadata,
 }
 
 impl From<crate::database::keys::TableKeys> for KeyInfo {
@@ -19,10 +61,20 @@ impl From<crate::database::keys::TableKeys> for KeyInfo {
         Self {
             name: value.name,
             id: value.id.inner(),
+            namespace: value.namespace,
             description: value.description,
+            kind: value.kind,
+            fields: Vec::new(),
+            file: None,
+            certificate: None,
+            versions: Vec::new(),
+            ssh: None,
+            totp: None,
             rotate_at: value.rotate_at,
-            secret: value.key.unwrap_or_default(),
-            rotate_with: value.rotate_with.unwrap_or_default(),
+            lease_ttl: value.lease_ttl,
+            honeytoken: value.honeytoken,
+            jwks_id: value.jwks_id,
+            metadata: value.metadata,
         }
     }
 }
@@ -31,27 +83,102 @@ impl From<crate::database::keys::TableKeys> for KeyInfo {
 pub async fn get_key(
     user: crate::auth::UserAuthRedirect,
     State(state): State<crate::AppState>,
+    Query(filter): Query<MetadataFilter>,
 ) -> Result<Html<String>, StatusCode> {
     let a = state.template_env.get_template("keys.html").to_status()?;
-    let user: super::UserInfo = user
-        .get_user(&state.db)
-        .await
-        .to_status()?
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
-        .into();
+    let org = user.org();
+    let user = super::UserInfo::fetch(user, &state.db).await?;
 
-    let all_keys = state
+    let mut all_keys = Vec::new();
+    for key in state
         .db
-        .get_all_keys()
+        .get_all_keys(org)
         .await
         .to_status()?
         .into_iter()
-        .map(Into::into)
-        .collect::<Vec<KeyInfo>>();
+        .filter(|k| filter.matches(&k.namespace, &k.name, &k.description, &k.metadata))
+    {
+        let id = key.id;
+        let mut info = KeyInfo::from(key);
+        match info.kind {
+            KeyKind::Text => (),
+            KeyKind::Fields => {
+                info.fields = state
+                    .db
+                    .get_all_key_fields(id)
+                    .await
+                    .to_status()?
+                    .into_iter()
+                    .map(|f| KeyFieldInfo {
+                        value: (f.kind != FieldKind::Secret && !info.honeytoken).then_some(f.value),
+                        name: f.name,
+                        kind: f.kind,
+                    })
+                    .collect();
+            }
+            KeyKind::File => {
+                info.file = state
+                    .db
+                    .fetch_key_file_info(id)
+                    .await
+                    .to_status()?
+                    .map(|f| KeyFile {
+                        filename: f.filename,
+                        content_type: f.content_type,
+                        size: f.size,
+                    });
+            }
+            KeyKind::Signing | KeyKind::Transit => {
+                info.versions = state
+                    .db
+                    .get_live_key_versions(id, crate::rotation::today())
+                    .await
+                    .to_status()?
+                    .into_iter()
+                    .map(|v| KeyVersion {
+                        kid: crate::signing::kid(v.version),
+                        algorithm: v.algorithm,
+                        created_at: v.created_at,
+                        retire_at: v.retire_at,
+                    })
+                    .collect();
+            }
+            KeyKind::Ssh => {
+                info.ssh = state
+                    .db
+                    .fetch_current_key_version(id)
+                    .await
+                    .to_status()?
+                    .map(|v| crate::ssh::SshPublicKey::from_private(&v.material))
+                    .transpose()
+                    .to_status()?;
+            }
+            KeyKind::Totp => {
+                info.totp = state
+                    .db
+                    .fetch_key_totp(id)
+                    .await
+                    .to_status()?
+                    .map(|t| t.seed.into());
+            }
+        }
+        info.certificate = state
+            .db
+            .fetch_key_cert(id)
+            .await
+            .to_status()?
+            .map(|c| c.cert);
+        all_keys.push(info);
+    }
+
+    let namespaces = state.db.get_all_namespaces(org).await.to_status()?;
 
     a.render(serde_json::json!({
         "self": user,
         "keys": all_keys,
+        "filter": filter,
+        "namespaces": namespaces,
+        "policies": state.config.secret_policies.keys().collect::<Vec<_>>(),
     }))
     .map(Html)
     .to_status()
@@ -10,6 +10,8 @@ mod client_all;
 mod client_key;
 mod index;
 mod key;
+mod report;
+mod share;
 
 macro_rules! serve_file_handler {
     ($state:expr, $path:expr) => {{ tower_http::services::ServeFile::new(format!("{}/{}", $state.config.static_dir, $path)) }};
@@ -39,26 +41,61 @@ pub fn router(state: AppState) -> axum::Router {
     axum::Router::new()
         .route("/", axum::routing::get(index::get_index))
         .route_with_tsr("/keys", axum::routing::get(key::get_key))
+        .route_with_tsr("/keys/report", axum::routing::get(report::get_report))
         .route_with_tsr("/clients", axum::routing::get(client_all::get_all_clients))
         .route_with_tsr(
             "/client/{id}",
             axum::routing::get(client_key::get_client_key),
         )
+        .route_with_tsr("/share", axum::routing::get(share::get_share))
         .with_state(state.clone())
         .merge(static_files_router(state.clone()))
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct OrgInfo {
+    id: i64,
+    name: String,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct UserInfo {
     name: String,
     id: i64,
+    /// the current org
+    org: i64,
+    orgs: Vec<OrgInfo>,
 }
 
-impl From<crate::database::users::TableUsers> for UserInfo {
-    fn from(value: crate::database::users::TableUsers) -> Self {
-        Self {
-            name: value.name,
-            id: value.id.inner(),
-        }
+impl UserInfo {
+    async fn fetch(
+        user: crate::auth::UserAuthRedirect,
+        db: &crate::database::Database,
+    ) -> Result<Self, axum::http::StatusCode> {
+        use crate::api::ErrorToStatusCode as _;
+
+        let org = user.org();
+        let user = user
+            .get_user(db)
+            .await
+            .to_status()?
+            .ok_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        let orgs = db
+            .get_all_orgs_from_user(user.id)
+            .await
+            .to_status()?
+            .into_iter()
+            .map(|o| OrgInfo {
+                id: o.id.inner(),
+                name: o.name,
+            })
+            .collect();
+
+        Ok(Self {
+            name: user.name,
+            id: user.id.inner(),
+            org: org.inner(),
+            orgs,
+        })
     }
 }
@@ -0,0 +1,26 @@
+use axum::{extract::State, http::StatusCode, response::Html};
+use tracing::*;
+
+use crate::api::ErrorToStatusCode as _;
+
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+pub async fn get_report(
+    user: crate::auth::UserAuthRedirect,
+    State(state): State<crate::AppState>,
+) -> Result<Html<String>, StatusCode> {
+    info!("Rendering report.html template");
+    let a = state.template_env.get_template("report.html").to_status()?;
+    let org = user.org();
+    let user = super::UserInfo::fetch(user, &state.db).await?;
+
+    let keys = state.db.get_all_keys(org).await.to_status()?;
+
+    a.render(serde_json::json!({
+        "self": user,
+        "report": crate::report::build(&keys),
+        "min_length": crate::report::MIN_LENGTH,
+        "min_entropy_bits": crate::report::MIN_ENTROPY_BITS,
+    }))
+    .map(Html)
+    .to_status()
+}
@@ -0,0 +1,15 @@
+use axum::{extract::State, http::StatusCode, response::Html};
+
+use crate::api::ErrorToStatusCode as _;
+
+// no auth, the token stays in the fragment and is only sent when revealing
+#[cfg_attr(debug_assertions, axum::debug_handler)]
+pub async fn get_share(State(state): State<crate::AppState>) -> Result<Html<String>, StatusCode> {
+    let a = state.template_env.get_template("share.html").to_status()?;
+
+    a.render(serde_json::json!({
+        "max_failed_attempts": crate::share::MAX_FAILED_ATTEMPTS,
+    }))
+    .map(Html)
+    .to_status()
+}
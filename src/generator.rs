@@ -0,0 +1,198 @@
+use base64::Engine;
+use color_eyre::{Result, eyre::eyre};
+use rand::{Rng, RngCore, seq::IndexedRandom, seq::SliceRandom};
+use std::collections::BTreeMap;
+
+/// Name of the policy that always exists, unless the config overrides it
+pub const DEFAULT_POLICY: &str = "default";
+
+const MAX_LENGTH: usize = 4096;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretFormat {
+    /// Random characters picked from the policy's classes
+    #[default]
+    Chars,
+    Hex,
+    Base64url,
+    /// A random (v4) UUID, the length and classes are ignored
+    Uuid,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    fn chars(self) -> &'static [u8] {
+        match self {
+            Self::Lower => b"abcdefghijklmnopqrstuvwxyz",
+            Self::Upper => b"ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+            Self::Digit => b"0123456789",
+            Self::Symbol => b"!#$%&*+-.:=?@^_~",
+        }
+    }
+}
+
+fn default_length() -> usize {
+    32
+}
+
+fn default_classes() -> Vec<CharClass> {
+    vec![CharClass::Lower, CharClass::Upper, CharClass::Digit]
+}
+
+/// How to generate a secret, policies are named in the config
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SecretPolicy {
+    #[serde(default)]
+    pub format: SecretFormat,
+    /// Length of the generated part, without the prefix
+    #[serde(default = "default_length")]
+    pub length: usize,
+    /// Every class is present at least once, only used by the `chars` format
+    #[serde(default = "default_classes")]
+    pub classes: Vec<CharClass>,
+    #[serde(default)]
+    pub prefix: String,
+}
+
+impl Default for SecretPolicy {
+    fn default() -> Self {
+        Self {
+            format: SecretFormat::default(),
+            length: default_length(),
+            classes: default_classes(),
+            prefix: String::new(),
+        }
+    }
+}
+
+impl SecretPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.format == SecretFormat::Uuid {
+            return Ok(());
+        }
+        if !(1..=MAX_LENGTH).contains(&self.length) {
+            return Err(eyre!("length must be between 1 and {MAX_LENGTH}"));
+        }
+        if self.format == SecretFormat::Chars {
+            if self.classes.is_empty() {
+                return Err(eyre!("at least one character class is needed"));
+            }
+            if self.length < self.classes.len() {
+                return Err(eyre!("length is shorter than the number of classes"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn generate(&self) -> String {
+        let mut rng = rand::rng();
+        let body = match self.format {
+            SecretFormat::Chars => {
+                // one of each class first, so none is missing, then shuffle them in
+                let all: Vec<u8> = self
+                    .classes
+                    .iter()
+                    .flat_map(|c| c.chars().iter().copied())
+                    .collect();
+                let mut out: Vec<u8> = self
+                    .classes
+                    .iter()
+                    .map(|c| *c.chars().choose(&mut rng).unwrap())
+                    .collect();
+                while out.len() < self.length {
+                    out.push(all[rng.random_range(0..all.len())]);
+                }
+                out.shuffle(&mut rng);
+                String::from_utf8(out).unwrap()
+            }
+            SecretFormat::Hex => {
+                let mut bytes = vec![0u8; self.length.div_ceil(2)];
+                rng.fill_bytes(&mut bytes);
+                let mut s = hex(&bytes);
+                s.truncate(self.length);
+                s
+            }
+            SecretFormat::Base64url => {
+                let mut bytes = vec![0u8; (self.length * 3).div_ceil(4)];
+                rng.fill_bytes(&mut bytes);
+                let mut s = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+                s.truncate(self.length);
+                s
+            }
+            SecretFormat::Uuid => {
+                let mut b = [0u8; 16];
+                rng.fill_bytes(&mut b);
+                b[6] = (b[6] & 0x0f) | 0x40;
+                b[8] = (b[8] & 0x3f) | 0x80;
+                let s = hex(&b);
+                format!(
+                    "{}-{}-{}-{}-{}",
+                    &s[..8],
+                    &s[8..12],
+                    &s[12..16],
+                    &s[16..20],
+                    &s[20..]
+                )
+            }
+        };
+        format!("{}{body}", self.prefix)
+    }
+}
+
+/// Alphanumeric token the server hands out (client secrets, wraps, shares, leases), `prefix`
+/// followed by `length` random characters
+pub fn random_token(prefix: &str, length: usize) -> String {
+    SecretPolicy {
+        format: SecretFormat::Chars,
+        length,
+        classes: default_classes(),
+        prefix: prefix.to_string(),
+    }
+    .generate()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for x in bytes {
+        use std::fmt::Write;
+        write!(s, "{x:02x}").unwrap();
+    }
+    s
+}
+
+/// Read the policies from a JSON file mapping names to policies,
+/// the `default` policy is added when the file doesn't define it
+pub fn load_policies(path: Option<&str>) -> Result<BTreeMap<String, SecretPolicy>> {
+    let mut policies: BTreeMap<String, SecretPolicy> = match path {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => BTreeMap::new(),
+    };
+    for (name, policy) in &policies {
+        policy
+            .validate()
+            .map_err(|e| e.wrap_err(format!("secret policy \"{name}\"")))?;
+    }
+    policies.entry(DEFAULT_POLICY.to_string()).or_default();
+    Ok(policies)
+}
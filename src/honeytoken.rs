@@ -0,0 +1,105 @@
+//! Canary keys: they look like any other credential, but nobody has a reason to touch them, so
+//! any access is audited with a high severity and alerted on. The request itself is served as
+//! usual, so whoever tripped it can't tell.
+
+use std::net::IpAddr;
+
+use tracing::error;
+
+use crate::database::audit::{AuditActor, AuditSeverity};
+use crate::database::clientkeys::ClientKeyId;
+use crate::database::keys::TableKeys;
+use crate::state::AppState;
+
+pub const AUDIT_RETRIEVED: &str = "honeytoken.retrieved";
+pub const AUDIT_REVEALED: &str = "honeytoken.revealed";
+pub const AUDIT_LEAKED: &str = "honeytoken.leaked";
+pub const AUDIT_SHARED: &str = "honeytoken.shared";
+
+/// How the honeytoken was touched
+#[derive(Debug, Clone)]
+pub enum Access {
+    /// By a client, through `get_key`
+    Retrieved(ClientKeyId),
+    /// Its secrets shown to a user
+    Revealed,
+    /// Found in a leak check, `found` as in the report
+    Leaked(String),
+    /// Opened through a share link
+    Shared,
+}
+
+/// Audit and alert on an access to `key` by `actor` from `ip`, if it is a honeytoken
+pub async fn check(
+    state: &AppState,
+    key: &TableKeys,
+    actor: AuditActor,
+    access: Access,
+    ip: Option<IpAddr>,
+) {
+    if !key.honeytoken {
+        return;
+    }
+    let (event, what) = match &access {
+        Access::Retrieved(link) => (
+            AUDIT_RETRIEVED,
+            format!("retrieved through link {}", link.inner()),
+        ),
+        Access::Revealed => (AUDIT_REVEALED, "revealed".to_string()),
+        Access::Leaked(found) => (AUDIT_LEAKED, format!("found ({found}) in a leak check")),
+        Access::Shared => (AUDIT_SHARED, "opened through a share link".to_string()),
+    };
+
+    if let Err(e) = state
+        .db
+        .log_audit(key.org, Some(key.id), actor, event, AuditSeverity::High)
+        .await
+    {
+        error!("Failed to audit honeytoken {}: {e}", key.id.inner());
+    }
+
+    let (user, client, by) = match actor {
+        AuditActor::User(user) => {
+            let name = match state.db.fetch_user(user).await {
+                Ok(Some(u)) => u.name,
+                _ => "?".to_string(),
+            };
+            (
+                Some(user.inner()),
+                None,
+                format!("user {} ({name})", user.inner()),
+            )
+        }
+        AuditActor::Client(client) => {
+            let name = match state.db.fetch_client_by_id(client).await {
+                Ok(Some(c)) => format!("{}/{}", c.namespace, c.name),
+                _ => "?".to_string(),
+            };
+            (
+                None,
+                Some(client.inner()),
+                format!("client {} ({name})", client.inner()),
+            )
+        }
+        AuditActor::Share(share) => (None, None, format!("share link {}", share.inner())),
+    };
+    crate::notify::alert(
+        state,
+        crate::notify::Alert {
+            event,
+            org: key.org.inner(),
+            key: Some(key.id.inner()),
+            client,
+            user,
+            ip,
+            message: format!(
+                "honeytoken {}/{} ({}) {what} by {by} from {}",
+                key.namespace,
+                key.name,
+                key.id.inner(),
+                ip.map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string()),
+            ),
+            at: chrono::Utc::now(),
+        },
+    );
+}
@@ -0,0 +1,49 @@
+//! Finding stored secrets in a blob of text (a log excerpt, a pasted config, ...).
+//!
+//! The text and the stored values are both reduced to SHA-256 digests before being compared,
+//! so how long a lookup takes doesn't depend on how much of a value matches, and the same
+//! matching keeps working if values end up stored hashed or encrypted.
+
+use sha2::Digest;
+use std::collections::HashSet;
+
+pub type Fingerprint = [u8; 32];
+
+/// Shorter values are too likely to show up by chance, they are never reported
+pub const MIN_LENGTH: usize = 8;
+
+// each level splits the parts of the previous one, and every part of every level is kept,
+// as values can hold the characters of the later levels (base64 padding, URLs, ...)
+const LEVELS: &[&[char]] = &[
+    &[
+        '"', '\'', '`', ',', ';', '(', ')', '[', ']', '{', '}', '<', '>',
+    ],
+    &['=', ':'],
+    &['@', '/', '?', '&'],
+];
+
+pub fn fingerprint(value: &str) -> Fingerprint {
+    sha2::Sha256::digest(value.as_bytes()).into()
+}
+
+/// Fingerprints of every token of `text` that could be a secret: its whitespace separated
+/// words, and their parts once split on quotes and brackets, then on `=` and `:`, then on
+/// the separators of URLs
+pub fn candidates(text: &str) -> HashSet<Fingerprint> {
+    let mut tokens: Vec<&str> = text.split_whitespace().collect();
+    let mut level = tokens.clone();
+    for separators in LEVELS {
+        level = level.iter().flat_map(|t| t.split(*separators)).collect();
+        tokens.extend(&level);
+    }
+    tokens
+        .into_iter()
+        .filter(|t| t.len() >= MIN_LENGTH)
+        .map(fingerprint)
+        .collect()
+}
+
+/// Whether a stored value appears in the text the candidates were taken from
+pub fn is_leaked(candidates: &HashSet<Fingerprint>, value: &str) -> bool {
+    value.len() >= MIN_LENGTH && candidates.contains(&fingerprint(value))
+}
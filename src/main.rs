@@ -1,11 +1,29 @@
 #![allow(unused)]
 
+mod accesstoken;
+mod allowlist;
 mod api;
 mod auth;
+mod certs;
+mod clientsecret;
 mod config;
 mod database;
 mod front;
+mod generator;
+mod honeytoken;
+mod leaks;
+mod notify;
+mod ratelimit;
+mod report;
+mod rotation;
+mod share;
+mod signedrequest;
+mod signing;
+mod ssh;
 mod state;
+mod tls;
+mod totp;
+mod transit;
 
 use crate::{config::Config, state::AppState};
 use color_eyre::Result;
@@ -32,16 +50,67 @@ pub fn router(state: AppState) -> OpenApiRouter {
             api::client::client_info,
             api::client::client_set_info
         ))
+        .routes(routes!(api::client::client_list))
         .routes(routes!(api::client::client_list_keys,))
         .routes(routes!(api::client::client_new,))
         .routes(routes!(api::client::client_new_key))
         .routes(routes!(api::client::client_new_secret))
+        .routes(routes!(api::client::client_rotate))
+        .routes(routes!(api::client::client_set_key_cidrs))
+        .routes(routes!(api::client::client_set_key_limits))
         .routes(routes!(api::key::get_key))
+        .routes(routes!(api::key::key_certificate))
+        .routes(routes!(api::key::key_audit))
         .routes(routes!(api::key::key_delete))
+        .routes(routes!(api::key::key_fields))
+        .routes(routes!(api::key::key_set_field))
+        .routes(routes!(api::key::key_ssh_public))
+        .routes(routes!(api::key::key_delete_field))
+        .routes(routes!(
+            api::key::key_upload_file,
+            api::key::key_download_file
+        ))
         .routes(routes!(api::key::key_info, api::key::key_set_info))
+        .routes(routes!(api::key::key_list))
         .routes(routes!(api::key::key_new))
+        .routes(routes!(api::key::key_report))
         .routes(routes!(api::key::key_rotate))
         .routes(routes!(api::key::key_update_secret, api::key::key_secret))
+        .routes(routes!(api::leaks::leaks_check))
+        .routes(routes!(api::lease::key_set_lease_ttl))
+        .routes(routes!(
+            api::lease::key_leases,
+            api::lease::key_revoke_leases
+        ))
+        .routes(routes!(api::lease::lease_renew))
+        .routes(routes!(api::org::org_add_member))
+        .routes(routes!(api::org::org_list))
+        .routes(routes!(api::org::org_members))
+        .routes(routes!(api::org::org_new))
+        .routes(routes!(api::org::org_remove_member))
+        .routes(routes!(api::secret::secret_generate))
+        .routes(routes!(api::secret::secret_policies))
+        .routes(routes!(api::share::key_share))
+        .routes(routes!(api::share::key_shares))
+        .routes(routes!(api::share::key_revoke_share))
+        .routes(routes!(api::share::share_open))
+        .routes(routes!(api::signing::sign))
+        .routes(routes!(api::token::client_token))
+        .routes(routes!(api::totp::key_set_totp, api::totp::key_totp_info))
+        .routes(routes!(api::totp::key_totp_code))
+        .routes(routes!(api::totp::totp_code))
+        .routes(routes!(api::transit::decrypt))
+        .routes(routes!(api::transit::encrypt))
+        .routes(routes!(api::transit::rewrap))
+        .routes(routes!(api::wrap::client_wrap_secret))
+        .routes(routes!(api::wrap::wrap_unwrap))
+        .with_state(state)
+}
+
+/// Public endpoints served at the root, outside of `/api/`
+pub fn well_known(state: AppState) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(api::signing::key_jwks))
         .with_state(state)
 }
 
@@ -50,9 +119,11 @@ async fn main() -> Result<()> {
     tracing_subscriber::fmt().with_max_level(Level::INFO).init();
     sqlx::any::install_default_drivers();
     let state = AppState::new(Config::from_env()?).await?;
+    rotation::spawn(state.clone());
 
     let (router, api) = OpenApiRouter::with_openapi(Api::openapi())
         .nest("/api/", router(state.clone()))
+        .merge(well_known(state.clone()))
         .split_for_parts();
 
     let router: axum::Router<()> = front::router(state.clone())
@@ -61,15 +132,22 @@ async fn main() -> Result<()> {
         .nest("/auth", auth::router(state.clone()))
         .nest_service("/static", ServeDir::new(state.config.static_dir.clone()));
 
+    let router = router.layer(
+        TraceLayer::new_for_http()
+            .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+            .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
+    );
+
     let socket = TcpListener::bind((state.config.ip, state.config.port)).await?;
-    axum::serve(
-        socket,
-        (router).layer(
-            TraceLayer::new_for_http()
-                .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
-                .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
-        ),
-    )
-    .await?;
+    match tls::from_config(&state.config)? {
+        Some(tls) => tls::serve(socket, tls, router).await?,
+        None => {
+            axum::serve(
+                socket,
+                router.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .await?
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,46 @@
+//! Security alerts: always logged, and POSTed as JSON to `ALERT_WEBHOOK` when it is set.
+
+use chrono::{DateTime, Utc};
+use tracing::{error, warn};
+
+use crate::state::AppState;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Alert {
+    /// Stable name of what happened, like the audited actions
+    pub event: &'static str,
+    pub org: i64,
+    pub key: Option<i64>,
+    pub client: Option<i64>,
+    pub user: Option<i64>,
+    /// Where the request raising the alert came from
+    pub ip: Option<std::net::IpAddr>,
+    /// For humans
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Raise the alert without waiting for the webhook, a slow or failing one only gets logged
+pub fn alert(state: &AppState, alert: Alert) {
+    warn!(
+        "ALERT {} from {:?}: {}",
+        alert.event, alert.ip, alert.message
+    );
+    let Some(url) = state.config.alert_webhook.clone() else {
+        return;
+    };
+    let http_client = state.http_client.clone();
+    tokio::spawn(async move {
+        let body = serde_json::to_vec(&alert).expect("alerts are always valid json");
+        let sent = http_client
+            .post(url)
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = sent {
+            error!("Failed to send alert {} to the webhook: {e}", alert.event);
+        }
+    });
+}
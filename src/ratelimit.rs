@@ -0,0 +1,290 @@
+//! In-memory request limits on the endpoints taking secrets, and lockout of the addresses
+//! guessing them.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::Config, state::AppState};
+
+// past this many tracked subjects, the stale ones are dropped on the next request
+const PRUNE_AT: usize = 10_000;
+
+/// What a credential gives access to, so every guess at it shares a limit, whatever was guessed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Credential {
+    /// A link (`clients_key`), from the id in its secret or signed request
+    Link(i64),
+    /// A client, from a verified access token
+    Client(i64),
+    /// A client certificate, by fingerprint
+    Certificate(String),
+    Share(i64),
+    Wrap(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Subject {
+    Ip(Option<IpAddr>),
+    Credential(Credential),
+}
+
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    count: u32,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// A request that went over a limit, answered with `429` and how long to wait
+#[derive(Debug, Clone, Copy)]
+pub struct Limited(pub Duration);
+
+impl IntoResponse for Limited {
+    fn into_response(self) -> Response {
+        let secs = self.0.as_secs() + u64::from(self.0.subsec_nanos() != 0);
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, secs.max(1).to_string())],
+        )
+            .into_response()
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests: u32,
+    window: Duration,
+    threshold: u32,
+    base: Duration,
+    max: Duration,
+    windows: Mutex<HashMap<Subject, Window>>,
+    failures: Mutex<HashMap<Option<IpAddr>, Failures>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            requests: config.rate_limit_requests,
+            window: Duration::from_secs(config.rate_limit_window_secs),
+            threshold: config.lockout_threshold,
+            base: Duration::from_secs(config.lockout_base_secs),
+            max: Duration::from_secs(config.lockout_max_secs),
+            windows: Mutex::default(),
+            failures: Mutex::default(),
+        }
+    }
+
+    /// Count a request from `ip`, refused while it is locked out
+    pub fn check(&self, ip: Option<IpAddr>) -> Result<(), Limited> {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: Option<IpAddr>, now: Instant) -> Result<(), Limited> {
+        if let Some(until) = self
+            .failures
+            .lock()
+            .unwrap()
+            .get(&ip)
+            .and_then(|f| f.locked_until)
+            .filter(|until| *until > now)
+        {
+            return Err(Limited(until - now));
+        }
+        self.count(Subject::Ip(ip), now)
+    }
+
+    /// Count a request using `credential`, from whatever address
+    pub fn check_credential(&self, credential: Credential) -> Result<(), Limited> {
+        self.count(Subject::Credential(credential), Instant::now())
+    }
+
+    fn count(&self, subject: Subject, now: Instant) -> Result<(), Limited> {
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > PRUNE_AT {
+            windows.retain(|_, w| now - w.start < self.window);
+        }
+        let w = windows.entry(subject).or_insert(Window {
+            start: now,
+            count: 0,
+        });
+        if now - w.start >= self.window {
+            *w = Window {
+                start: now,
+                count: 0,
+            };
+        }
+        w.count += 1;
+        if w.count > self.requests {
+            return Err(Limited(w.start + self.window - now));
+        }
+        Ok(())
+    }
+
+    /// An unknown secret was tried from `ip`, locking it out once past the threshold,
+    /// twice as long on each new failure. Requests that succeed in between don't make up for
+    /// it: only a quiet period does.
+    pub fn failure(&self, ip: Option<IpAddr>) {
+        self.failure_at(ip, Instant::now())
+    }
+
+    fn failure_at(&self, ip: Option<IpAddr>, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() > PRUNE_AT {
+            failures.retain(|_, f| now - f.last < self.max);
+        }
+        let f = failures.entry(ip).or_insert(Failures {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+        // forgiven after a quiet period as long as the longest lockout
+        if now - f.last >= self.max {
+            f.count = 0;
+        }
+        f.count += 1;
+        f.last = now;
+        if let Some(over) = f.count.checked_sub(self.threshold) {
+            let lockout = self
+                .base
+                .saturating_mul(2u32.saturating_pow(over))
+                .min(self.max);
+            f.locked_until = Some(now + lockout);
+            tracing::warn!(
+                "{ip:?} locked out for {lockout:?} after {} failures",
+                f.count
+            );
+        }
+    }
+}
+
+/// Per address limit for routes without a credential
+pub async fn limit_by_ip(
+    State(state): State<AppState>,
+    crate::allowlist::ClientIp(ip): crate::allowlist::ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    match state.limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(limited) => limited.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Option<IpAddr> = Some(IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1)));
+
+    fn limiter() -> RateLimiter {
+        RateLimiter {
+            requests: 1000,
+            window: Duration::from_secs(60),
+            threshold: 3,
+            base: Duration::from_secs(10),
+            max: Duration::from_secs(100),
+            windows: Mutex::default(),
+            failures: Mutex::default(),
+        }
+    }
+
+    #[test]
+    fn good_requests_dont_reset_failures() {
+        let limiter = limiter();
+        let now = Instant::now();
+        // a valid secret used between guesses, which are still counted
+        for i in 0..2 {
+            let now = now + Duration::from_secs(i);
+            assert!(limiter.check_at(IP, now).is_ok());
+            limiter.failure_at(IP, now);
+            assert!(limiter.check_at(IP, now).is_ok());
+        }
+        let now = now + Duration::from_secs(2);
+        limiter.failure_at(IP, now);
+        let Err(Limited(wait)) = limiter.check_at(IP, now) else {
+            panic!("not locked out after 3 failures");
+        };
+        assert_eq!(wait, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn lockout_expires_and_doubles() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.failure_at(IP, now);
+        }
+        assert!(limiter.check_at(IP, now + Duration::from_secs(9)).is_err());
+        let now = now + Duration::from_secs(10);
+        assert!(limiter.check_at(IP, now).is_ok());
+
+        limiter.failure_at(IP, now);
+        let Err(Limited(wait)) = limiter.check_at(IP, now) else {
+            panic!("not locked out again");
+        };
+        assert_eq!(wait, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn failures_are_forgotten_after_a_quiet_period() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            limiter.failure_at(IP, now);
+        }
+        let now = now + Duration::from_secs(100);
+        limiter.failure_at(IP, now);
+        assert!(limiter.check_at(IP, now).is_ok());
+    }
+
+    #[test]
+    fn credentials_are_limited_from_any_address() {
+        let limiter = RateLimiter {
+            requests: 2,
+            ..limiter()
+        };
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert!(
+                limiter
+                    .count(Subject::Credential(Credential::Link(1)), now)
+                    .is_ok()
+            );
+        }
+        assert!(
+            limiter
+                .count(Subject::Credential(Credential::Link(1)), now)
+                .is_err()
+        );
+        assert!(
+            limiter
+                .count(Subject::Credential(Credential::Link(2)), now)
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .count(
+                    Subject::Credential(Credential::Link(1)),
+                    now + Duration::from_secs(60)
+                )
+                .is_ok()
+        );
+    }
+}
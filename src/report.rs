@@ -0,0 +1,106 @@
+//! Hygiene report over the values of text keys: shared, weak or missing values.
+
+use std::collections::BTreeMap;
+
+use crate::database::keys::{KeyKind, TableKeys};
+
+/// Values shorter than this are reported as weak
+pub const MIN_LENGTH: usize = 16;
+/// Values with less (Shannon) entropy than this, in bits, are reported as weak
+pub const MIN_ENTROPY_BITS: f64 = 48.0;
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ReportedKey {
+    pub id: i64,
+    pub namespace: String,
+    pub name: String,
+}
+
+impl From<&TableKeys> for ReportedKey {
+    fn from(key: &TableKeys) -> Self {
+        Self {
+            id: key.id.inner(),
+            namespace: key.namespace.clone(),
+            name: key.name.clone(),
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Weakness {
+    Short,
+    LowEntropy,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct WeakKey {
+    pub key: ReportedKey,
+    pub length: usize,
+    pub entropy_bits: u32,
+    pub weaknesses: Vec<Weakness>,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct KeyReport {
+    /// Groups of keys holding the same value
+    pub duplicates: Vec<Vec<ReportedKey>>,
+    pub weak: Vec<WeakKey>,
+    /// Keys without a value
+    pub empty: Vec<ReportedKey>,
+}
+
+/// Shannon entropy of the characters of `value`, times its length
+pub fn entropy_bits(value: &str) -> f64 {
+    let mut counts = BTreeMap::<char, usize>::new();
+    for c in value.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = value.chars().count() as f64;
+    let per_char: f64 = counts
+        .values()
+        .map(|&n| n as f64 / len)
+        .map(|p| -p * p.log2())
+        .sum();
+    per_char * len
+}
+
+/// Only text keys are looked at, the other kinds don't keep their value in `keys`
+pub fn build(keys: &[TableKeys]) -> KeyReport {
+    let mut report = KeyReport::default();
+    let mut by_value = BTreeMap::<crate::leaks::Fingerprint, Vec<ReportedKey>>::new();
+
+    for key in keys.iter().filter(|k| k.kind == KeyKind::Text) {
+        let Some(value) = key.key.as_deref().filter(|v| !v.is_empty()) else {
+            report.empty.push(key.into());
+            continue;
+        };
+        by_value
+            .entry(crate::leaks::fingerprint(value))
+            .or_default()
+            .push(key.into());
+
+        let length = value.chars().count();
+        let entropy = entropy_bits(value);
+        let mut weaknesses = Vec::new();
+        if length < MIN_LENGTH {
+            weaknesses.push(Weakness::Short);
+        }
+        if entropy < MIN_ENTROPY_BITS {
+            weaknesses.push(Weakness::LowEntropy);
+        }
+        if !weaknesses.is_empty() {
+            report.weak.push(WeakKey {
+                key: key.into(),
+                length,
+                entropy_bits: entropy as u32,
+                weaknesses,
+            });
+        }
+    }
+
+    report.duplicates = by_value.into_values().filter(|k| k.len() > 1).collect();
+    report
+}
@@ -0,0 +1,148 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use color_eyre::{Result, eyre::eyre};
+use tracing::{error, info};
+
+use crate::database::{
+    Date,
+    keys::{KeyId, KeyKind, TableKeys},
+};
+use crate::signing::SigningAlgorithm;
+use crate::ssh::SshAlgorithm;
+use crate::state::AppState;
+
+/// How often the scheduler looks for keys whose `rotateAt` is due
+const CHECK_EVERY: Duration = Duration::from_secs(60 * 60);
+
+pub fn today() -> Date {
+    Date(chrono::Utc::now().date_naive())
+}
+
+/// Generate a new version of a signing key, it becomes the current one
+pub async fn new_signing_version(
+    state: &AppState,
+    key: KeyId,
+    algorithm: SigningAlgorithm,
+) -> Result<i64> {
+    // RSA key generation takes a while, keep it off the async workers
+    let material = tokio::task::spawn_blocking(move || algorithm.generate()).await??;
+    state
+        .db
+        .create_key_version(key, algorithm.as_str(), &material, today())
+        .await
+}
+
+/// Generate a new SSH keypair for a key, it becomes the one delivered to clients
+pub async fn new_ssh_version(
+    state: &AppState,
+    key: KeyId,
+    comment: String,
+    algorithm: SshAlgorithm,
+) -> Result<i64> {
+    let material = tokio::task::spawn_blocking(move || algorithm.generate(&comment)).await??;
+    state
+        .db
+        .create_key_version(key, algorithm.as_str(), material.as_bytes(), today())
+        .await
+}
+
+/// Generate a new version of a transit key, it becomes the one used to encrypt
+pub async fn new_transit_version(state: &AppState, key: KeyId) -> Result<i64> {
+    state
+        .db
+        .create_key_version(
+            key,
+            crate::transit::ALGORITHM,
+            &crate::transit::generate(),
+            today(),
+        )
+        .await
+}
+
+/// Add a new version to a managed key (`signing`, `transit` or `ssh`)
+///
+/// The previous versions of a signing key stay published for the grace period, the ones of a
+/// transit key are kept forever so old ciphertexts can still be decrypted, the ones of an SSH
+/// key are retired right away
+pub async fn rotate_managed_key(state: &AppState, key: &TableKeys) -> Result<i64> {
+    let version = match key.kind {
+        KeyKind::Signing => {
+            let algorithm = match state.db.fetch_current_key_version(key.id).await? {
+                Some(current) => SigningAlgorithm::from_str(&current.algorithm)?,
+                None => return Err(eyre!("signing key without any version")),
+            };
+            let version = new_signing_version(state, key.id, algorithm).await?;
+            let retire_at =
+                Date(today().0 + chrono::Days::new(state.config.signing_grace_days.into()));
+            state
+                .db
+                .retire_key_versions_before(key.id, version, retire_at)
+                .await?;
+            version
+        }
+        KeyKind::Transit => new_transit_version(state, key.id).await?,
+        KeyKind::Ssh => {
+            let algorithm = match state.db.fetch_current_key_version(key.id).await? {
+                Some(current) => SshAlgorithm::from_str(&current.algorithm)?,
+                None => SshAlgorithm::default(),
+            };
+            let version = new_ssh_version(state, key.id, key.name.clone(), algorithm).await?;
+            state
+                .db
+                .retire_key_versions_before(key.id, version, today())
+                .await?;
+            version
+        }
+        kind => return Err(eyre!("{} keys aren't managed", kind.as_str())),
+    };
+    state
+        .db
+        .update_key_secrets(key.id, None, Some(None), None)
+        .await?;
+    state.db.revoke_key_leases(key.id).await?;
+    Ok(version)
+}
+
+async fn rotate_due_keys(state: &AppState) -> Result<()> {
+    for kind in [KeyKind::Signing, KeyKind::Transit, KeyKind::Ssh] {
+        for key in state.db.get_all_keys_to_rotate(kind, today()).await? {
+            match rotate_managed_key(state, &key).await {
+                Ok(version) => info!(
+                    "rotated {} key {} to v{version}",
+                    kind.as_str(),
+                    key.id.inner()
+                ),
+                Err(e) => error!(
+                    "failed to rotate {} key {}: {e}",
+                    kind.as_str(),
+                    key.id.inner()
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rotate managed keys once their `rotateAt` is reached, and delete the expired links,
+/// for as long as the server runs
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_EVERY);
+        loop {
+            interval.tick().await;
+            if let Err(e) = rotate_due_keys(&state).await {
+                error!("key rotation failed: {e}");
+            }
+            match state
+                .db
+                .remove_expired_client_keys(chrono::Utc::now())
+                .await
+            {
+                Ok(0) => {}
+                Ok(n) => info!("deleted {n} expired or used up client links"),
+                Err(e) => error!("failed to delete expired client links: {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,64 @@
+//! One-time share links handing the value of a key to a person rather than a client.
+//!
+//! The link is `/share#<token>`: the token stays in the fragment, so it never reaches the
+//! server logs, and opening the page only shows a button, so link previews don't burn it. The
+//! optional passphrase is stored as `<salt>$<hash>`, base64 PBKDF2-HMAC-SHA256.
+
+use std::num::NonZeroU32;
+
+use base64::{Engine, engine::general_purpose::STANDARD_NO_PAD};
+
+use crate::generator::random_token;
+
+pub const PREFIX: &str = "aks_";
+
+const TOKEN_LENGTH: usize = 40;
+const SALT_LENGTH: usize = 16;
+const ITERATIONS: NonZeroU32 = NonZeroU32::new(600_000).unwrap();
+
+/// Wrong passphrases tried before the link burns
+pub const MAX_FAILED_ATTEMPTS: i64 = 5;
+
+pub fn new_token() -> String {
+    random_token(PREFIX, TOKEN_LENGTH)
+}
+
+/// Path of the page opening the share with `token`
+pub fn link(token: &str) -> String {
+    format!("/share#{token}")
+}
+
+pub fn hash_passphrase(passphrase: &str) -> String {
+    let mut salt = [0u8; SALT_LENGTH];
+    rand::fill(&mut salt);
+    let mut hash = [0u8; ring::digest::SHA256_OUTPUT_LEN];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        ITERATIONS,
+        &salt,
+        passphrase.as_bytes(),
+        &mut hash,
+    );
+    format!(
+        "{}${}",
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash)
+    )
+}
+
+pub fn verify_passphrase(stored: &str, passphrase: &str) -> bool {
+    let Some((salt, hash)) = stored.split_once('$') else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (STANDARD_NO_PAD.decode(salt), STANDARD_NO_PAD.decode(hash)) else {
+        return false;
+    };
+    ring::pbkdf2::verify(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        ITERATIONS,
+        &salt,
+        passphrase.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
@@ -0,0 +1,221 @@
+//! Requests signed with a client secret, so the secret itself never travels over the wire.
+//!
+//! The client names the link its secret belongs to, and sends the current unix time, a fresh
+//! nonce, and the HMAC-SHA256 (hex) with the secret of `METHOD\npath?query\ntimestamp\nnonce`.
+//! Stale timestamps and nonces already seen for the link are refused.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::http::{HeaderMap, Method, Uri};
+
+pub const LINK_HEADER: &str = "x-akm-link";
+pub const TIMESTAMP_HEADER: &str = "x-akm-timestamp";
+pub const NONCE_HEADER: &str = "x-akm-nonce";
+pub const SIGNATURE_HEADER: &str = "x-akm-signature";
+
+/// Nonces are kept in memory for a while, they must stay short
+const NONCE_LENGTH: std::ops::RangeInclusive<usize> = 16..=64;
+// past this many remembered nonces, the stale ones are dropped on the next request
+const PRUNE_AT: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct SignedRequest {
+    /// The link whose secret signed the request
+    pub link: i64,
+    pub timestamp: i64,
+    pub nonce: String,
+    signature: Vec<u8>,
+    message: String,
+}
+
+/// What gets signed
+pub fn message(method: &Method, path_and_query: &str, timestamp: i64, nonce: &str) -> String {
+    format!("{method}\n{path_and_query}\n{timestamp}\n{nonce}")
+}
+
+pub fn sign(secret: &str, message: &str) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    ring::hmac::sign(&key, message.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl SignedRequest {
+    /// `None` when the request doesn't carry the signature headers, or they are malformed.
+    /// `uri` is the one the client sent, before the routers nesting the handler stripped it
+    pub fn from_head(method: &Method, uri: &Uri, headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name)?.to_str().ok().map(str::trim);
+
+        let link = header(LINK_HEADER)?.parse().ok()?;
+        let timestamp = header(TIMESTAMP_HEADER)?.parse().ok()?;
+        let nonce = header(NONCE_HEADER)?;
+        if !NONCE_LENGTH.contains(&nonce.len()) {
+            return None;
+        }
+        let signature = from_hex(header(SIGNATURE_HEADER)?)?;
+        let path_and_query = uri.path_and_query().map_or("/", |p| p.as_str());
+
+        Some(Self {
+            link,
+            timestamp,
+            nonce: nonce.to_string(),
+            signature,
+            message: message(method, path_and_query, timestamp, nonce),
+        })
+    }
+
+    /// Whether the timestamp is within `max_skew` seconds of `now`, either way
+    pub fn is_fresh(&self, now: i64, max_skew: u64) -> bool {
+        self.timestamp.abs_diff(now) <= max_skew
+    }
+
+    pub fn verify(&self, secret: &str) -> bool {
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+        ring::hmac::verify(&key, self.message.as_bytes(), &self.signature).is_ok()
+    }
+}
+
+/// Nonces of the signed requests, remembered until their timestamp is stale
+#[derive(Debug, Default)]
+pub struct NonceCache {
+    seen: Mutex<HashMap<(i64, String), i64>>,
+}
+
+impl NonceCache {
+    /// Remember the nonce of a fresh request, `false` if the link already used it
+    pub fn insert(&self, request: &SignedRequest, now: i64, max_skew: u64) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() > PRUNE_AT {
+            seen.retain(|_, timestamp| timestamp.abs_diff(now) <= max_skew);
+        }
+        seen.insert((request.link, request.nonce.clone()), request.timestamp)
+            .is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "akm_1_secret";
+    const NONCE: &str = "0123456789abcdef";
+    const NOW: i64 = 1_700_000_000;
+
+    fn headers(link: &str, timestamp: i64, nonce: &str, signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(LINK_HEADER, link.parse().unwrap());
+        headers.insert(TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+        headers.insert(NONCE_HEADER, nonce.parse().unwrap());
+        headers.insert(SIGNATURE_HEADER, signature.parse().unwrap());
+        headers
+    }
+
+    // the headers a client sends to `method` `uri` at `timestamp`
+    fn signed(method: &Method, uri: &str, timestamp: i64, nonce: &str) -> HeaderMap {
+        let signature = sign(SECRET, &message(method, uri, timestamp, nonce));
+        headers("1", timestamp, nonce, &signature)
+    }
+
+    fn request(method: &Method, uri: &str, headers: &HeaderMap) -> SignedRequest {
+        SignedRequest::from_head(method, &uri.parse().unwrap(), headers).unwrap()
+    }
+
+    #[test]
+    fn valid_signature_is_verified() {
+        let headers = signed(&Method::GET, "/api/get?name=db", NOW, NONCE);
+        let request = request(&Method::GET, "/api/get?name=db", &headers);
+        assert_eq!((request.link, request.timestamp), (1, NOW));
+        assert!(request.verify(SECRET));
+        assert!(!request.verify("akm_1_other"));
+    }
+
+    #[test]
+    fn tampered_request_is_refused() {
+        let headers = signed(&Method::GET, "/api/get?name=db", NOW, NONCE);
+        for (method, uri) in [
+            (Method::POST, "/api/get?name=db"),
+            (Method::GET, "/api/other?name=db"),
+            (Method::GET, "/api/get?name=prod"),
+            (Method::GET, "/api/get"),
+        ] {
+            assert!(!request(&method, uri, &headers).verify(SECRET));
+        }
+
+        let mut later = headers.clone();
+        later.insert(TIMESTAMP_HEADER, (NOW + 1).to_string().parse().unwrap());
+        assert!(!request(&Method::GET, "/api/get?name=db", &later).verify(SECRET));
+        let mut other_nonce = headers;
+        other_nonce.insert(NONCE_HEADER, "fedcba9876543210".parse().unwrap());
+        assert!(!request(&Method::GET, "/api/get?name=db", &other_nonce).verify(SECRET));
+    }
+
+    #[test]
+    fn skew_is_allowed_up_to_the_bound() {
+        for (timestamp, fresh) in [
+            (NOW, true),
+            (NOW - 30, true),
+            (NOW + 30, true),
+            (NOW - 31, false),
+            (NOW + 31, false),
+        ] {
+            let headers = signed(&Method::GET, "/", timestamp, NONCE);
+            assert_eq!(
+                request(&Method::GET, "/", &headers).is_fresh(NOW, 30),
+                fresh
+            );
+        }
+    }
+
+    #[test]
+    fn reused_nonce_is_refused_per_link() {
+        let cache = NonceCache::default();
+        let headers = signed(&Method::GET, "/", NOW, NONCE);
+        let first = request(&Method::GET, "/", &headers);
+        assert!(cache.insert(&first, NOW, 30));
+        assert!(!cache.insert(&first, NOW + 1, 30));
+
+        // a new request with the same nonce, still refused
+        let again = request(
+            &Method::POST,
+            "/",
+            &signed(&Method::POST, "/", NOW + 2, NONCE),
+        );
+        assert!(!cache.insert(&again, NOW + 2, 30));
+
+        // another link may use the same nonce
+        let mut other = headers;
+        other.insert(LINK_HEADER, "2".parse().unwrap());
+        assert!(cache.insert(&request(&Method::GET, "/", &other), NOW, 30));
+    }
+
+    #[test]
+    fn malformed_headers_are_refused() {
+        let uri = "/".parse().unwrap();
+        let valid = sign(SECRET, &message(&Method::GET, "/", NOW, NONCE));
+        for headers in [
+            headers("1", NOW, NONCE, &valid[1..]),
+            headers("1", NOW, NONCE, &format!("{}zz", &valid[2..])),
+            headers("1", NOW, NONCE, &format!("{}é", &valid[2..])),
+            headers("x", NOW, NONCE, &valid),
+            headers("1", NOW, "short", &valid),
+            headers("1", NOW, &"n".repeat(65), &valid),
+        ] {
+            assert!(SignedRequest::from_head(&Method::GET, &uri, &headers).is_none());
+        }
+        let mut missing = headers("1", NOW, NONCE, &valid);
+        missing.remove(SIGNATURE_HEADER);
+        assert!(SignedRequest::from_head(&Method::GET, &uri, &missing).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use color_eyre::{Result, eyre::eyre};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use rsa::rand_core::OsRng;
+use rsa::traits::PublicKeyParts;
+use std::str::FromStr;
+
+/// Size of the generated RSA keys, in bits
+const RSA_BITS: usize = 2048;
+
+/// Algorithms of the signing keys managed by akm, named after their JOSE `alg`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+pub enum SigningAlgorithm {
+    /// Ed25519
+    EdDSA,
+    /// ECDSA on P-256 with SHA-256
+    ES256,
+    /// RSASSA-PKCS1-v1_5 with SHA-256
+    RS256,
+}
+
+impl SigningAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SigningAlgorithm::EdDSA => "EdDSA",
+            SigningAlgorithm::ES256 => "ES256",
+            SigningAlgorithm::RS256 => "RS256",
+        }
+    }
+
+    /// A new private key, PKCS#8 DER encoded
+    pub fn generate(self) -> Result<Vec<u8>> {
+        let der = match self {
+            SigningAlgorithm::EdDSA => ed25519_dalek::SigningKey::generate(&mut OsRng)
+                .to_pkcs8_der()?
+                .as_bytes()
+                .to_vec(),
+            SigningAlgorithm::ES256 => p256::SecretKey::random(&mut OsRng)
+                .to_pkcs8_der()?
+                .as_bytes()
+                .to_vec(),
+            SigningAlgorithm::RS256 => rsa::pkcs8::EncodePrivateKey::to_pkcs8_der(
+                &rsa::RsaPrivateKey::new(&mut OsRng, RSA_BITS)?,
+            )?
+            .as_bytes()
+            .to_vec(),
+        };
+        Ok(der)
+    }
+
+    /// The public half of `private` as a JWK
+    pub fn public_jwk(self, private: &[u8], kid: &str) -> Result<serde_json::Value> {
+        let mut jwk = match self {
+            SigningAlgorithm::EdDSA => {
+                let key = ed25519_dalek::SigningKey::from_pkcs8_der(private)?;
+                serde_json::json!({
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": URL_SAFE_NO_PAD.encode(key.verifying_key().as_bytes()),
+                })
+            }
+            SigningAlgorithm::ES256 => {
+                let key = p256::SecretKey::from_pkcs8_der(private)?;
+                let point = p256::elliptic_curve::sec1::ToEncodedPoint::to_encoded_point(
+                    &key.public_key(),
+                    false,
+                );
+                let (Some(x), Some(y)) = (point.x(), point.y()) else {
+                    return Err(eyre!("P-256 public key is the identity point"));
+                };
+                serde_json::json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "x": URL_SAFE_NO_PAD.encode(x),
+                    "y": URL_SAFE_NO_PAD.encode(y),
+                })
+            }
+            SigningAlgorithm::RS256 => {
+                let key =
+                    <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_der(private)?;
+                serde_json::json!({
+                    "kty": "RSA",
+                    "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+                    "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+                })
+            }
+        };
+        jwk["kid"] = kid.into();
+        jwk["alg"] = self.as_str().into();
+        jwk["use"] = "sig".into();
+        Ok(jwk)
+    }
+}
+
+impl SigningAlgorithm {
+    /// Sign `payload` with `private`, in the format expected by JWS
+    /// (raw 64 bytes for EdDSA, `r || s` for ES256, PKCS#1 v1.5 for RS256)
+    pub fn sign(self, private: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+        use rsa::signature::{SignatureEncoding, Signer};
+
+        let signature = match self {
+            SigningAlgorithm::EdDSA => ed25519_dalek::SigningKey::from_pkcs8_der(private)?
+                .sign(payload)
+                .to_vec(),
+            SigningAlgorithm::ES256 => {
+                let signature: p256::ecdsa::Signature =
+                    p256::ecdsa::SigningKey::from_pkcs8_der(private)?.sign(payload);
+                signature.to_vec()
+            }
+            SigningAlgorithm::RS256 => {
+                let key =
+                    <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::from_pkcs8_der(private)?;
+                rsa::pkcs1v15::SigningKey::<rsa::sha2::Sha256>::new(key)
+                    .sign(payload)
+                    .to_vec()
+            }
+        };
+        Ok(signature)
+    }
+}
+
+impl FromStr for SigningAlgorithm {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "EdDSA" => Ok(SigningAlgorithm::EdDSA),
+            "ES256" => Ok(SigningAlgorithm::ES256),
+            "RS256" => Ok(SigningAlgorithm::RS256),
+            _ => Err(eyre!("unknown signing algorithm: {s}")),
+        }
+    }
+}
+
+/// Random id addressing the public JWKS of a signing key, so the public endpoint doesn't let
+/// anyone list the signing keys of every org by their sequential ids
+pub fn new_jwks_id() -> String {
+    crate::generator::SecretPolicy {
+        format: crate::generator::SecretFormat::Chars,
+        length: 32,
+        classes: vec![
+            crate::generator::CharClass::Lower,
+            crate::generator::CharClass::Digit,
+        ],
+        prefix: String::new(),
+    }
+    .generate()
+}
+
+/// The `kid` of a version of a signing key, unique within its key set
+pub fn kid(version: i64) -> String {
+    format!("v{version}")
+}
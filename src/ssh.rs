@@ -0,0 +1,78 @@
+use color_eyre::{Result, eyre::eyre};
+use rsa::rand_core::OsRng;
+use ssh_key::{Algorithm, HashAlg, LineEnding, PrivateKey};
+use std::str::FromStr;
+
+/// Algorithms of the SSH keypairs managed by akm
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SshAlgorithm {
+    #[default]
+    Ed25519,
+    Rsa,
+}
+
+impl SshAlgorithm {
+    /// Name of the algorithm in `authorized_keys`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SshAlgorithm::Ed25519 => "ssh-ed25519",
+            SshAlgorithm::Rsa => "ssh-rsa",
+        }
+    }
+
+    /// A new private key in the OpenSSH format, `comment` ends up in the public key
+    pub fn generate(self, comment: &str) -> Result<String> {
+        let algorithm = match self {
+            SshAlgorithm::Ed25519 => Algorithm::Ed25519,
+            SshAlgorithm::Rsa => Algorithm::Rsa {
+                hash: Some(HashAlg::Sha512),
+            },
+        };
+        let mut key = PrivateKey::random(&mut OsRng, algorithm)?;
+        key.set_comment(comment);
+        Ok(key.to_openssh(LineEnding::LF)?.to_string())
+    }
+}
+
+impl FromStr for SshAlgorithm {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ssh-ed25519" => Ok(SshAlgorithm::Ed25519),
+            "ssh-rsa" => Ok(SshAlgorithm::Rsa),
+            _ => Err(eyre!("unknown ssh algorithm: {s}")),
+        }
+    }
+}
+
+/// What can be shown about an SSH keypair, without its private half
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SshPublicKey {
+    /// In the `authorized_keys` format
+    pub public_key: String,
+    /// `SHA256:...`, as shown by `ssh-keygen -l`
+    pub fingerprint: String,
+}
+
+impl SshPublicKey {
+    pub fn from_private(private: &[u8]) -> Result<Self> {
+        let key = PrivateKey::from_openssh(private)?;
+        Ok(Self {
+            public_key: key.public_key().to_openssh()?,
+            fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+        })
+    }
+}
@@ -21,8 +21,12 @@ pub struct AppState {
         >,
     >,
     pub key: Key,
+    /// Signs access tokens, see [`crate::accesstoken::derive_key`]
+    pub token_key: [u8; 32],
     pub http_client: openidconnect::reqwest::Client,
     pub template_env: minijinja::Environment<'static>,
+    pub limiter: Arc<crate::ratelimit::RateLimiter>,
+    pub nonces: Arc<crate::signedrequest::NonceCache>,
 }
 
 impl AppState {
@@ -31,6 +35,7 @@ impl AppState {
     pub async fn new(config: Config) -> Result<Self> {
         let config: Arc<Config> = Arc::new(config);
         let key = Key::try_from(config.cookie_secret.as_slice())?;
+        let token_key = crate::accesstoken::derive_key(&config.cookie_secret);
         let db = crate::database::Database::new(&config.db).await?;
         let http_client = openidconnect::reqwest::ClientBuilder::new()
             // Following redirects opens the client up to SSRF vulnerabilities.
@@ -65,9 +70,12 @@ impl AppState {
         }
 
         Ok(Self {
+            limiter: Arc::new(crate::ratelimit::RateLimiter::new(&config)),
+            nonces: Arc::default(),
             db,
             config,
             key,
+            token_key,
             oauth2: Arc::new(client),
             http_client,
             template_env,
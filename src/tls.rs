@@ -0,0 +1,270 @@
+//! TLS termination, with optional client certificates clients can authenticate with
+//! instead of their bearer secrets.
+
+use std::{path::Path, sync::Arc};
+
+use axum::extract::ConnectInfo;
+use color_eyre::{Result, eyre::eyre};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::{
+    RootCertStore, ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::WebPkiClientVerifier,
+};
+use sha2::Digest;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+use tracing::{debug, error};
+
+/// The certificate a client presented during the handshake, already verified against the
+/// configured CA. Set as a request extension
+#[derive(Clone, Debug)]
+pub struct PeerCertificate {
+    der: Vec<u8>,
+}
+
+impl PeerCertificate {
+    pub fn new(der: Vec<u8>) -> Self {
+        Self { der }
+    }
+
+    /// Lowercase hex SHA-256 of the DER encoding, as registered on the client
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.der)
+    }
+
+    pub fn sans(&self) -> Vec<String> {
+        crate::certs::CertInfo::parse(&self.der)
+            .map(|c| c.sans)
+            .unwrap_or_default()
+    }
+}
+
+pub fn fingerprint(der: &[u8]) -> String {
+    let mut s = String::with_capacity(64);
+    for x in sha2::Sha256::digest(der) {
+        use std::fmt::Write;
+        write!(s, "{x:02x}").unwrap();
+    }
+    s
+}
+
+/// Server certificate chain and key from PEM files. With `client_ca`, clients may present a
+/// certificate issued by it, but aren't required to (browsers don't)
+pub fn server_config(
+    cert: impl AsRef<Path>,
+    key: impl AsRef<Path>,
+    client_ca: Option<impl AsRef<Path>>,
+) -> Result<Arc<ServerConfig>> {
+    let chain = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+    let roots = client_ca
+        .map(|ca| {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(ca)? {
+                roots.add(cert?)?;
+            }
+            Ok::<_, color_eyre::Report>(roots)
+        })
+        .transpose()?;
+    build_server_config(chain, key, roots)
+}
+
+pub(crate) fn build_server_config(
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_roots: Option<RootCertStore>,
+) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match client_roots {
+        Some(roots) => builder.with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()?,
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_single_cert(chain, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Like `axum::serve`, over TLS. Requests carry the peer address as `ConnectInfo`, and
+/// its verified certificate as `PeerCertificate` when it presented one
+pub async fn serve(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    router: axum::Router,
+) -> Result<()> {
+    let acceptor = tokio_rustls::TlsAcceptor::from(config);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let router = router.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(s) => s,
+                Err(e) => {
+                    debug!("TLS handshake with {peer} failed: {e}");
+                    return;
+                }
+            };
+            let cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|c| c.first())
+                .map(|c| PeerCertificate::new(c.to_vec()));
+
+            let service = hyper::service::service_fn(
+                move |mut request: hyper::Request<hyper::body::Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo(peer));
+                    if let Some(cert) = &cert {
+                        request.extensions_mut().insert(cert.clone());
+                    }
+                    router.clone().oneshot(request)
+                },
+            );
+            if let Err(e) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Connection with {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+/// Whether TLS is configured, both the certificate and its key are needed
+pub fn from_config(config: &crate::config::Config) -> Result<Option<Arc<ServerConfig>>> {
+    match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            server_config(cert, key, config.tls_client_ca.as_ref()).map(Some)
+        }
+        (None, None) => Ok(None),
+        _ => Err(eyre!("TLS_CERT and TLS_KEY must be set together")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Handshakes against certificates of a local CA, generated for each test
+
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use rustls::{ClientConfig, pki_types::ServerName};
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    use super::*;
+
+    struct Ca {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    impl Ca {
+        fn new(name: &str) -> Self {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, name);
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let cert = params.self_signed(&key).unwrap();
+            Self { cert, key }
+        }
+
+        fn issue(
+            &self,
+            san: &str,
+            usage: ExtendedKeyUsagePurpose,
+        ) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![san.to_string()]).unwrap();
+            params.extended_key_usages = vec![usage];
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+            (
+                cert.der().clone(),
+                PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+            )
+        }
+
+        fn roots(&self) -> RootCertStore {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.cert.der().clone()).unwrap();
+            roots
+        }
+    }
+
+    // handshake of a client presenting `client` with a server trusting `ca` for clients,
+    // returning the certificate the server saw
+    async fn handshake(
+        ca: &Ca,
+        client: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+    ) -> std::io::Result<Option<PeerCertificate>> {
+        let (server_cert, server_key) = ca.issue("akm.test", ExtendedKeyUsagePurpose::ServerAuth);
+        let server = build_server_config(vec![server_cert], server_key, Some(ca.roots())).unwrap();
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(ca.roots());
+        let config = match client {
+            Some((cert, key)) => builder.with_client_auth_cert(vec![cert], key).unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let accept = async {
+            let stream = TlsAcceptor::from(server).accept(server_io).await?;
+            Ok(stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|c| c.first())
+                .map(|c| PeerCertificate::new(c.to_vec())))
+        };
+        let connect = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("akm.test").unwrap(), client_io);
+        // with TLS 1.3 the client is done before the server looked at its certificate,
+        // so only the server side tells whether it was accepted
+        let (_client, peer) = tokio::join!(connect, accept);
+        peer
+    }
+
+    #[tokio::test]
+    async fn client_certificate_of_the_ca() {
+        let ca = Ca::new("akm test CA");
+        let (cert, key) = ca.issue("client.akm.test", ExtendedKeyUsagePurpose::ClientAuth);
+        let expected = fingerprint(&cert);
+
+        let peer = handshake(&ca, Some((cert, key))).await.unwrap().unwrap();
+        assert_eq!(peer.fingerprint(), expected);
+        assert_eq!(peer.fingerprint().len(), 64);
+        assert_eq!(peer.sans(), vec!["client.akm.test".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn no_client_certificate() {
+        let ca = Ca::new("akm test CA");
+        assert!(handshake(&ca, None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn client_certificate_of_another_ca() {
+        let ca = Ca::new("akm test CA");
+        let other = Ca::new("another CA");
+        let client = other.issue("client.akm.test", ExtendedKeyUsagePurpose::ClientAuth);
+        assert!(handshake(&ca, Some(client)).await.is_err());
+    }
+}
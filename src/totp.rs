@@ -0,0 +1,150 @@
+use color_eyre::{Result, eyre::eyre};
+use std::str::FromStr;
+use totp_rs::{Secret, TOTP};
+
+/// Hash used to compute the codes, most authenticators only support SHA1
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+pub enum TotpAlgorithm {
+    #[default]
+    SHA1,
+    SHA256,
+    SHA512,
+}
+
+impl TotpAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TotpAlgorithm::SHA1 => "SHA1",
+            TotpAlgorithm::SHA256 => "SHA256",
+            TotpAlgorithm::SHA512 => "SHA512",
+        }
+    }
+}
+
+impl FromStr for TotpAlgorithm {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "SHA1" => Ok(TotpAlgorithm::SHA1),
+            "SHA256" => Ok(TotpAlgorithm::SHA256),
+            "SHA512" => Ok(TotpAlgorithm::SHA512),
+            _ => Err(eyre!("unknown totp algorithm: {s}")),
+        }
+    }
+}
+
+impl From<TotpAlgorithm> for totp_rs::Algorithm {
+    fn from(value: TotpAlgorithm) -> Self {
+        match value {
+            TotpAlgorithm::SHA1 => totp_rs::Algorithm::SHA1,
+            TotpAlgorithm::SHA256 => totp_rs::Algorithm::SHA256,
+            TotpAlgorithm::SHA512 => totp_rs::Algorithm::SHA512,
+        }
+    }
+}
+
+fn default_digits() -> u32 {
+    6
+}
+
+fn default_period() -> u32 {
+    30
+}
+
+/// Everything needed to compute the codes of a TOTP
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TotpSeed {
+    /// Base32, as shown by most sites next to their QR code
+    pub secret: String,
+    #[serde(default)]
+    pub algorithm: TotpAlgorithm,
+    /// Between 6 and 8
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    /// Seconds a code is valid for
+    #[serde(default = "default_period")]
+    pub period: u32,
+    #[serde(default)]
+    pub issuer: Option<String>,
+    #[serde(default)]
+    pub account: Option<String>,
+}
+
+/// A code and how long it stays valid
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TotpCode {
+    pub code: String,
+    /// Seconds before the next code
+    pub expires_in: u64,
+}
+
+impl TotpSeed {
+    /// Import an `otpauth://totp/...` URI, as encoded in the QR codes
+    pub fn from_uri(uri: &str) -> Result<Self> {
+        let totp = TOTP::from_url_unchecked(uri)?;
+        let seed = Self {
+            secret: totp.get_secret_base32(),
+            algorithm: match totp.algorithm {
+                totp_rs::Algorithm::SHA1 => TotpAlgorithm::SHA1,
+                totp_rs::Algorithm::SHA256 => TotpAlgorithm::SHA256,
+                totp_rs::Algorithm::SHA512 => TotpAlgorithm::SHA512,
+            },
+            digits: totp.digits.try_into()?,
+            period: totp.step.try_into()?,
+            issuer: totp.issuer.clone(),
+            account: Some(totp.account_name.clone()).filter(|s| !s.is_empty()),
+        };
+        seed.totp()?;
+        Ok(seed)
+    }
+
+    // short (80 bits) secrets are still common, so the length isn't checked
+    fn totp(&self) -> Result<TOTP> {
+        if !(6..=8).contains(&self.digits) {
+            return Err(eyre!("totp codes must have between 6 and 8 digits"));
+        }
+        if self.period == 0 {
+            return Err(eyre!("totp period can't be 0"));
+        }
+        let secret = Secret::Encoded(self.secret.trim().replace(' ', "").to_uppercase())
+            .to_bytes()
+            .map_err(|e| eyre!("totp secret isn't base32: {e:?}"))?;
+        if secret.is_empty() {
+            return Err(eyre!("totp secret is empty"));
+        }
+        Ok(TOTP::new_unchecked(
+            self.algorithm.into(),
+            self.digits as usize,
+            1,
+            self.period.into(),
+            secret,
+            self.issuer.clone(),
+            self.account.clone().unwrap_or_default(),
+        ))
+    }
+
+    /// Check the seed can be used to compute codes
+    pub fn validate(&self) -> Result<()> {
+        self.totp().map(|_| ())
+    }
+
+    pub fn current_code(&self) -> Result<TotpCode> {
+        let totp = self.totp()?;
+        Ok(TotpCode {
+            code: totp.generate_current()?,
+            expires_in: totp.ttl()?,
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use color_eyre::{Result, eyre::eyre};
+
+/// Algorithm of the data-encryption keys, stored with each version
+pub const ALGORITHM: &str = "aes256-gcm";
+
+/// Every ciphertext starts with this, followed by the key version: `akm:v<version>:<base64>`
+const PREFIX: &str = "akm:v";
+
+const NONCE_LEN: usize = 12;
+
+/// A new random data-encryption key
+pub fn generate() -> Vec<u8> {
+    Aes256Gcm::generate_key(OsRng).to_vec()
+}
+
+/// Encrypt `plaintext` with `version` of a key, the nonce is prepended to the ciphertext
+pub fn encrypt(material: &[u8], version: i64, plaintext: &[u8]) -> Result<String> {
+    let cipher = Aes256Gcm::new_from_slice(material)?;
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let mut data = nonce.to_vec();
+    data.extend(
+        cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| eyre!("encryption failed: {e}"))?,
+    );
+    Ok(format!("{PREFIX}{version}:{}", STANDARD.encode(data)))
+}
+
+/// The version of the key a ciphertext was encrypted with, and its raw bytes
+pub fn parse(ciphertext: &str) -> Option<(i64, Vec<u8>)> {
+    let (version, data) = ciphertext.strip_prefix(PREFIX)?.split_once(':')?;
+    let data = STANDARD.decode(data).ok()?;
+    (data.len() > NONCE_LEN).then_some((version.parse().ok()?, data))
+}
+
+/// Decrypt the raw bytes of a ciphertext (as returned by [`parse`])
+pub fn decrypt(material: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(material)?;
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| eyre!("decryption failed: {e}"))
+}
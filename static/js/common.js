@@ -92,6 +92,24 @@ async function api_delete(url, body = null) {
 	return JSON.parse(text);
 }
 
+// pop the metadata fields out of a form's entries and return them as an object
+function formToMetadata(data) {
+	const opt = (name) => {
+		const v = (data.get(name) ?? "").trim();
+		data.delete(name);
+		return v.length == 0 ? null : v;
+	};
+	const tags = (data.get("tags") ?? "").split(",").map(t => t.trim()).filter(t => t.length != 0);
+	data.delete("tags");
+	return {
+		tags: tags,
+		owner: opt("owner"),
+		contact: opt("contact"),
+		provider: opt("provider"),
+		runbook: opt("runbook"),
+		environment: opt("environment"),
+	};
+}
 
 document.querySelectorAll(".spoiler").forEach(el => {
 	el.addEventListener("click", () => {
@@ -104,3 +122,4 @@ window.api_put = api_put;
 window.api_get = api_get;
 window.api_delete = api_delete;
 window.triggerToast = triggerToast;
+window.formToMetadata = formToMetadata;